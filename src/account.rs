@@ -1,145 +1,1192 @@
-use crate::Error;
+use crate::{
+    address::AddressLink,
+    amount::{Amount, Lamports, TokenAmount},
+    error::{parse_number, OrMissing, ScrapeError},
+    page::{wait_for_change, wait_for_page, Wait},
+    transaction::parse_timestamp,
+};
 use chrono::{DateTime, NaiveDateTime, Utc};
-use fantoccini::Client;
+use fantoccini::{error::CmdError, Client, Locator};
 use select::{
     document::Document,
+    node::Node,
     predicate::{Class, Name, Predicate},
 };
 use serde::Serialize;
-use std::{thread, time::Duration};
+use std::{collections::HashMap, sync::Arc};
 use tokio::sync::Mutex;
+use url::Url;
 
-#[derive(Default, Debug, Serialize)]
+#[derive(Debug, Serialize)]
 pub struct AccountDetails {
     pub address: String,
-    pub balance: f64,
+    /// What the account holds, with the details the explorer shows for it.
+    pub kind: AccountKind,
+    /// The token accounts listed on the Tokens tab. `None` for accounts without
+    /// that tab, for pages parsed offline, and when the tab didn't load.
+    pub token_holdings: Option<Vec<TokenHolding>>,
+    /// The inflation rewards on the first page of the Rewards tab, newest first.
+    /// `None` for accounts without that tab, for pages parsed offline, and when
+    /// the tab didn't load.
+    pub rewards: Option<Vec<InflationReward>>,
+    pub recent_transactions: Vec<Transaction>,
+}
+
+/// The kinds of account the explorer renders a dedicated overview for.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountKind {
+    /// An account owned by the System Program.
+    SystemWallet(GenericAccount),
+    TokenAccount(TokenAccount),
+    Mint(Mint),
+    /// A program deployed with the upgradeable BPF loader.
+    Program(UpgradeableProgram),
+    /// The account holding the executable data of an upgradeable program.
+    ProgramData(ProgramData),
+    Stake(StakeAccount),
+    Vote(VoteAccount),
+    Nonce(NonceAccount),
+    AddressLookupTable(LookupTableAccount),
+    /// Any other account, shown without decoding its data.
+    Other(OtherAccount),
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct GenericAccount {
+    pub balance: Lamports,
     pub owner: String,
-    pub data_size: f64,
+    pub owner_label: Option<String>,
+    /// Allocated data size in bytes.
+    pub data_size: u64,
     pub executable: bool,
-    pub recent_transactions: Vec<Transaction>,
+}
+
+/// An account without a kind of its own. Its overview varies, so only the
+/// generic rows it happens to show are read.
+#[derive(Default, Debug, Serialize)]
+pub struct OtherAccount {
+    /// Title of the overview card, e.g. `Overview` or `Program Buffer Account`.
+    pub overview: String,
+    pub balance: Option<Lamports>,
+    pub owner: Option<String>,
+    pub owner_label: Option<String>,
+    /// Data size in bytes.
+    pub data_size: Option<u64>,
+    pub executable: Option<bool>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct TokenAccount {
+    pub mint: String,
+    pub mint_label: Option<String>,
+    pub owner: String,
+    pub owner_label: Option<String>,
+    /// With the mint's decimals, see [`MintDecimals`].
+    pub balance: TokenAmount,
+    /// Shown instead of the usual initialized state, e.g. `Frozen`.
+    pub status: Option<String>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct Mint {
+    /// The name the explorer knows the token by, from the token registry or
+    /// the token's metadata.
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    /// The exact supply, with the mint's decimals.
+    pub supply: TokenAmount,
+    /// `None` once the supply is fixed.
+    pub mint_authority: Option<String>,
+    pub freeze_authority: Option<String>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct UpgradeableProgram {
+    pub balance: Lamports,
+    /// The account holding the program's executable data.
+    pub program_data: String,
+    pub upgradeable: bool,
+    /// `None` once the program is immutable.
+    pub upgrade_authority: Option<String>,
+    pub last_deployed_slot: u64,
+    /// Size of the executable data in bytes, read from the program data
+    /// account. `None` for pages parsed offline, and when that page didn't load.
+    pub executable_data_size: Option<u64>,
+    pub verified_build: Option<VerifiedBuild>,
+    /// `None` when the program doesn't embed a security.txt.
+    pub security_txt: Option<SecurityTxt>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct VerifiedBuild {
+    /// Whether the deployed program matches a verifiable build of its source.
+    pub verified: bool,
+    /// Where the explorer links to for the build details.
+    pub url: Option<String>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct SecurityTxt {
+    /// The fields in page order, read from the Security tab. Empty for pages
+    /// parsed offline, and when the tab didn't load.
+    pub fields: Vec<SecurityTxtField>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct SecurityTxtField {
+    pub name: String,
+    /// The value as displayed, with list items on separate lines.
+    pub value: String,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct ProgramData {
+    pub balance: Lamports,
+    /// Size of the executable data in bytes.
+    pub data_size: u64,
+    pub upgradeable: bool,
+    /// `None` once the program is immutable.
+    pub upgrade_authority: Option<String>,
+    pub last_deployed_slot: u64,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct StakeAccount {
+    pub balance: Lamports,
+    pub rent_reserve: Lamports,
+    /// As displayed, e.g. `Active`, `Deactivating` or `Not delegated`.
+    pub status: String,
+    pub stake_authority: String,
+    pub withdraw_authority: String,
+    /// `None` until the stake is delegated.
+    pub delegation: Option<StakeDelegation>,
+    /// `None` when the account isn't locked up.
+    pub lockup: Option<Lockup>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct StakeDelegation {
+    /// The vote account the stake is delegated to.
+    pub voter: String,
+    pub delegated_stake: Lamports,
+    /// Shown while the explorer knows the activation state.
+    pub active_stake: Option<Lamports>,
+    pub inactive_stake: Option<Lamports>,
+    pub activation_epoch: Option<u64>,
+    /// `None` until the stake is deactivated.
+    pub deactivation_epoch: Option<u64>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct Lockup {
+    pub expiry_epoch: u64,
+    pub expiry_timestamp: DateTime<Utc>,
+    pub expiry_unix_timestamp: i64,
+    /// The account that can lift the lockup early.
+    pub custodian: String,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct VoteAccount {
+    pub balance: Lamports,
+    pub authorized_voters: Vec<String>,
+    pub authorized_withdrawer: String,
+    pub last_timestamp: DateTime<Utc>,
+    pub last_unix_timestamp: i64,
+    /// Share of the rewards kept by the validator, in percent.
+    pub commission: u8,
+    pub root_slot: Option<u64>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct NonceAccount {
+    pub balance: Lamports,
+    pub authority: String,
+    /// The stored durable nonce.
+    pub blockhash: String,
+    pub fee_per_signature: Lamports,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct LookupTableAccount {
+    pub balance: Lamports,
+    pub active: bool,
+    /// `None` until the first addresses are added.
+    pub last_extended_slot: Option<u64>,
+    /// `None` once the table is frozen.
+    pub authority: Option<String>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct TokenHolding {
+    /// The token account.
+    pub address: String,
+    pub mint: String,
+    /// The token's name, when the explorer knows it.
+    pub label: Option<String>,
+    /// With the mint's decimals, see [`MintDecimals`].
+    pub balance: TokenAmount,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct InflationReward {
+    pub epoch: u64,
+    pub effective_slot: u64,
+    pub amount: Lamports,
+    pub post_balance: Lamports,
+    /// The balance change the reward made, in percent.
+    pub percent_change: Amount<i64>,
 }
 
 #[derive(Default, Debug, Serialize)]
 pub struct Transaction {
     pub signature: String,
     pub block: u64,
-    pub time: String,
+    pub time: DateTime<Utc>,
+    pub unix_timestamp: i64,
     pub result: String,
 }
 
+/// Which part of an account's transaction history to collect, newest first.
+#[derive(Debug, Default, Clone)]
+pub struct HistoryRange {
+    /// Maximum number of transactions to collect.
+    pub limit: usize,
+    /// Stop at this signature. The transaction itself isn't included.
+    pub until: Option<String>,
+    /// Stop at the first transaction older than this.
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl HistoryRange {
+    pub fn new(limit: usize) -> Self {
+        HistoryRange {
+            limit,
+            ..Default::default()
+        }
+    }
+}
+
 pub async fn get_account_info(
     url: &str,
-    txns_limit: usize,
-    wait_time: u64,
+    history: &HistoryRange,
+    hide_zero_balances: bool,
+    wait: &Wait,
+    mints: &MintDecimals,
     client: &Mutex<Client>,
-) -> Result<AccountDetails, Error> {
+) -> Result<AccountDetails, ScrapeError> {
     log::info!("Parsing data for url: {}", url);
     let mut webdriver = client.lock().await;
     webdriver.goto(url).await?;
     log::info!("Hold on. Waiting for page load...");
-    thread::sleep(Duration::from_secs(wait_time));
-    let html = webdriver.source().await?;
+    let mut html = wait_for_page(&mut webdriver, wait).await?;
 
-    let document = Document::from(html.as_str());
-    let mut table = document.find(Class("table-responsive").descendant(Name("tr")));
-    let address = table
-        .next()
-        .unwrap()
-        .find(Class("font-monospace").descendant(Name("span")))
-        .next()
-        .unwrap()
-        .text();
-    let balance = table
-        .next()
-        .unwrap()
-        .find(Class("font-monospace"))
-        .next()
-        .unwrap()
-        .text()
-        .parse::<f64>()
-        .unwrap();
-    let data_size = table
-        .next()
-        .unwrap()
-        .find(Class("text-lg-end"))
-        .next()
-        .unwrap()
-        .text()
-        .split_whitespace()
-        .next()
-        .unwrap()
-        .parse::<f64>()
-        .unwrap();
-    let owner = table
-        .next()
-        .unwrap()
-        .find(Class("font-monospace").descendant(Name("a")))
-        .next()
-        .unwrap()
-        .text();
-    let executable = table
-        .next()
-        .unwrap()
-        .find(Class("text-lg-end"))
+    // The explorer renders one page of history at a time, keep loading more
+    // until we have what was asked for or there's nothing left.
+    loop {
+        let document = Document::from(html.as_str());
+        let (_, complete) = parse_history(&document, history)?;
+        if complete || !can_load_more(&document) {
+            break;
+        }
+
+        let loaded = history_rows(&document).count();
+        log::info!("Loaded {} transactions, loading more...", loaded);
+        webdriver
+            .find(Locator::XPath(LOAD_MORE_BUTTON))
+            .await?
+            .click()
+            .await?;
+        let more = wait_for_change(&mut webdriver, wait, |document| {
+            history_rows(document).count() > loaded || !can_load_more(document)
+        })
+        .await;
+        match more {
+            Ok(more) => html = more,
+            Err(ScrapeError::Timeout { seconds }) => {
+                log::warn!(
+                    "Older transactions of {} didn't load within {}s, keeping the {} loaded",
+                    url,
+                    seconds,
+                    loaded
+                );
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let mut details = parse_account_html(&html, history)?;
+
+    let (tokens_tab, rewards_tab, security_tab, program_data, mint) = {
+        let document = Document::from(html.as_str());
+        let program_data = match &details.kind {
+            AccountKind::Program(program) => address_href(&document, &program.program_data),
+            _ => None,
+        };
+        let mint = match &details.kind {
+            AccountKind::TokenAccount(token) => address_href(&document, &token.mint),
+            _ => None,
+        };
+        (
+            tab_href(&document, "Tokens"),
+            tab_href(&document, "Rewards"),
+            tab_href(&document, "Security"),
+            program_data,
+            mint,
+        )
+    };
+
+    if let (AccountKind::TokenAccount(token), Some(href)) = (&mut details.kind, mint) {
+        let url = link_url(&mut webdriver, &href).await?;
+        let decimals = mints.get(&mut webdriver, &token.mint, &url, wait).await?;
+        token.balance = token.balance.with_decimals(decimals).ok_or_else(|| {
+            ScrapeError::layout("Overview: Token balance", "more decimals than its mint")
+        })?;
+    }
+
+    if let Some(href) = tokens_tab {
+        // The detailed view lists each token account rather than a total per mint.
+        let mut url = link_url(&mut webdriver, &href).await?;
+        url.query_pairs_mut().append_pair("display", "detail");
+        let html = load_extra_page(&mut webdriver, &url, "token holdings", wait, |document| {
+            holdings_card(document).is_some() || has_no_holdings(document)
+        })
+        .await?;
+        if let Some(html) = html {
+            let mut holdings = parse_token_holdings_html(&html, hide_zero_balances)?;
+            let mint_hrefs = {
+                let document = Document::from(html.as_str());
+                holdings
+                    .iter()
+                    .map(|x| address_href(&document, &x.mint))
+                    .collect::<Vec<_>>()
+            };
+            for (index, (holding, href)) in holdings.iter_mut().zip(mint_hrefs).enumerate() {
+                let section = format!("Token Holdings row {}", index + 1);
+                let url = link_url(&mut webdriver, &href.or_missing(&section, "a")?).await?;
+                let decimals = mints.get(&mut webdriver, &holding.mint, &url, wait).await?;
+                holding.balance = holding
+                    .balance
+                    .with_decimals(decimals)
+                    .ok_or_else(|| ScrapeError::layout(&section, "more decimals than its mint"))?;
+            }
+            details.token_holdings = Some(holdings);
+        }
+    }
+
+    if let Some(href) = rewards_tab {
+        let url = link_url(&mut webdriver, &href).await?;
+        let html = load_extra_page(&mut webdriver, &url, "rewards", wait, |document| {
+            rewards_card(document).is_some() || has_no_rewards(document)
+        })
+        .await?;
+        if let Some(html) = html {
+            details.rewards = Some(parse_rewards_html(&html)?);
+        }
+    }
+
+    if let AccountKind::Program(program) = &mut details.kind {
+        if let Some(href) = program_data {
+            let url = link_url(&mut webdriver, &href).await?;
+            let name = "the program's executable data account";
+            if let Some(html) = load_extra_page(&mut webdriver, &url, name, wait, |_| true).await? {
+                if let (_, AccountKind::ProgramData(data)) =
+                    parse_overview(&Document::from(html.as_str()))?
+                {
+                    program.executable_data_size = Some(data.data_size);
+                }
+            }
+        }
+
+        if let (Some(security_txt), Some(href)) = (&mut program.security_txt, security_tab) {
+            let url = link_url(&mut webdriver, &href).await?;
+            let html = load_extra_page(&mut webdriver, &url, "security.txt", wait, |document| {
+                titled_card(document, "Security.txt").is_some()
+            })
+            .await?;
+            if let Some(html) = html {
+                security_txt.fields = parse_security_txt_html(&html)?;
+            }
+        }
+    }
+
+    Ok(details)
+}
+
+/// The decimals of each mint, read from the mint's page the first time an
+/// amount of it is scraped. The explorer shows token amounts with only as many
+/// decimals as they need, so they're brought to their mint's decimals with it.
+/// Clones share their cache, e.g. between the sessions scraping a batch.
+#[derive(Debug, Default, Clone)]
+pub struct MintDecimals(Arc<Mutex<HashMap<String, u8>>>);
+
+impl MintDecimals {
+    /// The decimals of `mint`, loading its page at `url` unless already known.
+    pub async fn get(
+        &self,
+        webdriver: &mut Client,
+        mint: &str,
+        url: &Url,
+        wait: &Wait,
+    ) -> Result<u8, ScrapeError> {
+        let known = self.0.lock().await.get(mint).copied();
+        if let Some(decimals) = known {
+            return Ok(decimals);
+        }
+
+        log::info!("Loading the decimals of mint {}...", mint);
+        webdriver.goto(url.as_str()).await?;
+        let html = wait_for_page(webdriver, wait).await?;
+        let decimals = match parse_overview(&Document::from(html.as_str()))? {
+            (_, AccountKind::Mint(details)) => details.supply.decimals,
+            _ => {
+                let section = format!("Mint {}", mint);
+                return Err(ScrapeError::layout(&section, "not a token mint"));
+            }
+        };
+        self.0.lock().await.insert(mint.to_string(), decimals);
+        Ok(decimals)
+    }
+}
+
+/// Opens one of the pages the account details are completed with, and waits
+/// until `ready` holds for it. When the page doesn't load, logs a warning and
+/// returns `None` so the details scraped so far are kept.
+async fn load_extra_page(
+    webdriver: &mut Client,
+    url: &Url,
+    name: &str,
+    wait: &Wait,
+    ready: impl Fn(&Document) -> bool,
+) -> Result<Option<String>, ScrapeError> {
+    log::info!("Loading {}...", name);
+    webdriver.goto(url.as_str()).await?;
+    match wait_for_change(webdriver, wait, ready).await {
+        Ok(html) => Ok(Some(html)),
+        Err(
+            err @ (ScrapeError::Timeout { .. }
+            | ScrapeError::NotFound { .. }
+            | ScrapeError::Explorer { .. }),
+        ) => {
+            log::warn!("Couldn't load {} of {}, leaving it out: {}", name, url, err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Resolves a link found on the current page.
+async fn link_url(webdriver: &mut Client, href: &str) -> Result<Url, ScrapeError> {
+    let url = webdriver
+        .current_url()
+        .await?
+        .join(href)
+        .map_err(CmdError::from)?;
+    Ok(url)
+}
+
+/// Parses a rendered explorer account page.
+pub fn parse_account_html(
+    html: &str,
+    history: &HistoryRange,
+) -> Result<AccountDetails, ScrapeError> {
+    let document = Document::from(html);
+    let (address, kind) = parse_overview(&document)?;
+    let (recent_transactions, _) = parse_history(&document, history)?;
+
+    Ok(AccountDetails {
+        address,
+        kind,
+        token_holdings: None,
+        rewards: None,
+        recent_transactions,
+    })
+}
+
+/// Parses the overview card into the account's address and kind.
+fn parse_overview(document: &Document) -> Result<(String, AccountKind), ScrapeError> {
+    let overview = document
+        .find(Class("card"))
+        .find(|x| x.find(Class("card-header-title")).next().is_some())
+        .or_missing("Overview", ".card-header-title")?;
+    let title = overview
+        .find(Class("card-header-title"))
         .next()
-        .unwrap()
+        .or_missing("Overview", ".card-header-title")?
         .text();
-    let executable = match executable.trim().to_ascii_lowercase().as_str() {
-        "no" => false,
-        "yes" => true,
-        _ => panic!("Unexpected result"),
+    let title = title.trim();
+    let rows = Rows::of(&overview, title);
+
+    let address = rows.text("Address")?;
+    let kind = match title {
+        // Mints with an entry in the token registry are titled Overview as well.
+        "Overview" if rows.has("Decimals") => AccountKind::Mint(parse_mint(document, &rows)?),
+        "Overview"
+            if rows
+                .link("Assigned Program Id")
+                .is_ok_and(|x| x.pubkey == SYSTEM_PROGRAM) =>
+        {
+            AccountKind::SystemWallet(parse_generic_account(&rows)?)
+        }
+        "Token Mint" => AccountKind::Mint(parse_mint(document, &rows)?),
+        "Token Account" => AccountKind::TokenAccount(parse_token_account(&rows)?),
+        "Program Account" => AccountKind::Program(parse_program(&rows)?),
+        "Program Executable Data Account" => AccountKind::ProgramData(ProgramData {
+            balance: rows.balance("Balance")?,
+            data_size: parse_data_size(&rows.text("Data Size")?)?,
+            upgradeable: rows.yes_no("Upgradeable")?,
+            upgrade_authority: rows.optional_row_link("Upgrade Authority")?,
+            last_deployed_slot: rows
+                .number_link("Last Deployed Slot")?
+                .or_missing(&rows.field("Last Deployed Slot"), "a")?,
+        }),
+        "Stake Account" => AccountKind::Stake(parse_stake_account(document, &rows)?),
+        "Vote Account" => AccountKind::Vote(parse_vote_account(&rows)?),
+        "Nonce Account" => AccountKind::Nonce(parse_nonce_account(&rows)?),
+        "Address Lookup Table Account" => {
+            AccountKind::AddressLookupTable(parse_lookup_table(&rows)?)
+        }
+        // Buffers, sysvars, config accounts and others the explorer shows
+        // without a dedicated kind here.
+        other => AccountKind::Other(parse_other_account(other, &rows)?),
+    };
+
+    Ok((address, kind))
+}
+
+/// Parses the detailed view of an account's Tokens tab.
+pub fn parse_token_holdings_html(
+    html: &str,
+    hide_zero_balances: bool,
+) -> Result<Vec<TokenHolding>, ScrapeError> {
+    let document = Document::from(html);
+    let mut holdings = vec![];
+    let card = match holdings_card(&document) {
+        Some(card) => card,
+        None if has_no_holdings(&document) => return Ok(holdings),
+        None => return Err(ScrapeError::missing("Token Holdings", ".list")),
+    };
+
+    for (index, row) in card
+        .find(Class("list"))
+        .flat_map(|list| list.children().filter(|x| x.is(Name("tr"))))
+        .enumerate()
+    {
+        let section = format!("Token Holdings row {}", index + 1);
+        let mut links = row.find(Name("a")).filter(AddressLink::is_address_link);
+        let address = AddressLink::parse(&links.next().or_missing(&section, "a")?, &section)?;
+        let mint = AddressLink::parse(&links.next().or_missing(&section, "a")?, &section)?;
+        // The balance is followed by the token's symbol when it has one.
+        let balance = row
+            .find(Name("td"))
+            .last()
+            .or_missing(&section, "td")?
+            .text();
+        let balance = balance.split_whitespace().next().unwrap_or_default();
+        let balance = TokenAmount::parse(balance, "token balance")?;
+
+        if hide_zero_balances && balance.raw == 0 {
+            continue;
+        }
+        holdings.push(TokenHolding {
+            address: address.pubkey,
+            mint: mint.pubkey,
+            label: mint.label,
+            balance,
+        });
+    }
+
+    Ok(holdings)
+}
+
+fn holdings_card(document: &Document) -> Option<Node<'_>> {
+    titled_card(document, "Token Holdings").filter(|card| card.find(Class("list")).next().is_some())
+}
+
+/// Parses an account's Rewards tab.
+pub fn parse_rewards_html(html: &str) -> Result<Vec<InflationReward>, ScrapeError> {
+    let document = Document::from(html);
+    let mut rewards = vec![];
+    let card = match rewards_card(&document) {
+        Some(card) => card,
+        None if has_no_rewards(&document) => return Ok(rewards),
+        None => return Err(ScrapeError::missing("Rewards", ".list")),
+    };
+
+    for (index, row) in card
+        .find(Class("list"))
+        .flat_map(|list| list.children().filter(|x| x.is(Name("tr"))))
+        .enumerate()
+    {
+        let section = format!("Rewards row {}", index + 1);
+        let mut cells = row.find(Name("td")).map(|x| x.text().trim().to_string());
+        let mut next = |selector| cells.next().or_missing(&section, selector);
+        let epoch = parse_number(&next("td epoch")?, "epoch")?;
+        let effective_slot = parse_number(&next("td slot")?, "effective_slot")?;
+        let amount = Lamports::parse(&next("td amount")?, "reward amount")?;
+        let post_balance = Lamports::parse(&next("td post balance")?, "post_balance")?;
+        let percent_change = next("td percent change")?;
+        let percent_change = Amount::parse(percent_change.trim_end_matches('%'), "percent_change")?;
+
+        rewards.push(InflationReward {
+            epoch,
+            effective_slot,
+            amount,
+            post_balance,
+            percent_change,
+        });
+    }
+
+    Ok(rewards)
+}
+
+fn rewards_card(document: &Document) -> Option<Node<'_>> {
+    titled_card(document, "Rewards").filter(|card| card.find(Class("list")).next().is_some())
+}
+
+fn has_no_rewards(document: &Document) -> bool {
+    document
+        .find(Class("card-body"))
+        .any(|x| x.text().contains("No rewards issued"))
+}
+
+/// Parses a program's Security tab.
+pub fn parse_security_txt_html(html: &str) -> Result<Vec<SecurityTxtField>, ScrapeError> {
+    let document = Document::from(html);
+    let card = titled_card(&document, "Security.txt").or_missing("Security.txt", ".card")?;
+    let rows = Rows::of(&card, "Security.txt");
+
+    rows.rows
+        .iter()
+        .map(|(name, row)| {
+            let value = row
+                .find(Name("td"))
+                .nth(1)
+                .or_missing(&rows.field(name), "td")?;
+            let items = value.find(Name("li")).map(|x| x.text()).collect::<Vec<_>>();
+            let value = if items.is_empty() {
+                value.text()
+            } else {
+                items.join("\n")
+            };
+            Ok(SecurityTxtField {
+                name: name.clone(),
+                value: value.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn titled_card<'a>(document: &'a Document, title: &str) -> Option<Node<'a>> {
+    document.find(Class("card")).find(|card| {
+        card.find(Class("card-header-title"))
+            .next()
+            .is_some_and(|x| x.text().trim() == title)
+    })
+}
+
+fn has_no_holdings(document: &Document) -> bool {
+    document
+        .find(Class("card-body"))
+        .any(|x| x.text().contains("No token holdings"))
+}
+
+/// The href of the first link to `address` on the page.
+fn address_href(document: &Document, address: &str) -> Option<String> {
+    document
+        .find(Name("a"))
+        .filter(AddressLink::is_address_link)
+        .find(|x| AddressLink::parse(x, "").is_ok_and(|x| x.pubkey == address))?
+        .attr("href")
+        .map(String::from)
+}
+
+/// The link of one of the tabs below the overview, e.g. `Tokens`.
+fn tab_href(document: &Document, tab: &str) -> Option<String> {
+    document
+        .find(Class("nav-tabs").descendant(Class("nav-link")))
+        .find(|x| x.text().trim() == tab)?
+        .attr("href")
+        .map(String::from)
+}
+
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+
+fn parse_generic_account(rows: &Rows) -> Result<GenericAccount, ScrapeError> {
+    let owner = rows.link("Assigned Program Id")?;
+
+    Ok(GenericAccount {
+        balance: rows.balance("Balance")?,
+        owner: owner.pubkey,
+        owner_label: owner.label,
+        data_size: parse_data_size(&rows.text("Allocated Data Size")?)?,
+        executable: rows.yes_no("Executable")?,
+    })
+}
+
+fn parse_other_account(title: &str, rows: &Rows) -> Result<OtherAccount, ScrapeError> {
+    let owner = ["Assigned Program Id", "Owner"]
+        .into_iter()
+        .find(|x| rows.has(x))
+        .map(|x| rows.link(x))
+        .transpose()?;
+    let data_size = ["Allocated Data Size", "Data Size"]
+        .into_iter()
+        .find(|x| rows.has(x))
+        .map(|x| parse_data_size(&rows.text(x)?))
+        .transpose()?;
+    let executable = if rows.has("Executable") {
+        Some(rows.yes_no("Executable")?)
+    } else {
+        None
+    };
+
+    Ok(OtherAccount {
+        overview: title.to_string(),
+        balance: rows.optional_balance("Balance")?,
+        owner_label: owner.as_ref().and_then(|x| x.label.clone()),
+        owner: owner.map(|x| x.pubkey),
+        data_size,
+        executable,
+    })
+}
+
+fn parse_program(rows: &Rows) -> Result<UpgradeableProgram, ScrapeError> {
+    let verified_build = if rows.has("Verified Build") {
+        let badge = rows.value("Verified Build")?;
+        let status = badge.text();
+        Some(VerifiedBuild {
+            verified: !status.contains("Unverified") && status.contains("Verified"),
+            url: badge
+                .find(Name("a"))
+                .next()
+                .and_then(|x| x.attr("href"))
+                .map(String::from),
+        })
+    } else {
+        None
+    };
+    // The fields themselves are on the Security tab.
+    let security_txt = if rows.has("Security.txt") && rows.text("Security.txt")? == "Included" {
+        Some(SecurityTxt::default())
+    } else {
+        None
+    };
+
+    Ok(UpgradeableProgram {
+        balance: rows.balance("Balance")?,
+        program_data: rows.link("Executable Data")?.pubkey,
+        upgradeable: rows.yes_no("Upgradeable")?,
+        upgrade_authority: rows.optional_row_link("Upgrade Authority")?,
+        last_deployed_slot: rows
+            .number_link("Last Deployed Slot")?
+            .or_missing(&rows.field("Last Deployed Slot"), "a")?,
+        executable_data_size: None,
+        verified_build,
+        security_txt,
+    })
+}
+
+fn parse_mint(document: &Document, rows: &Rows) -> Result<Mint, ScrapeError> {
+    let decimals = parse_number(&rows.text("Decimals")?, "decimals")?;
+    // Mints without a mint authority show a Fixed Supply instead.
+    let supply = if rows.has("Fixed Supply") {
+        rows.text("Fixed Supply")?
+    } else {
+        rows.text("Current Supply")?
+    };
+    let supply = TokenAmount::parse(&supply, "supply")?
+        .with_decimals(decimals)
+        .ok_or_else(|| ScrapeError::UnparsableNumber {
+            field: "supply".to_string(),
+            raw: supply.clone(),
+        })?;
+    let (name, symbol) = parse_token_header(document);
+
+    Ok(Mint {
+        name,
+        symbol,
+        supply,
+        mint_authority: rows.optional_row_link("Mint Authority")?,
+        freeze_authority: rows.optional_row_link("Freeze Authority")?,
+    })
+}
+
+/// Reads a token's name and symbol from the page header. The symbol is shown
+/// below the name when the token has metadata.
+fn parse_token_header(document: &Document) -> (Option<String>, Option<String>) {
+    // Tokens the explorer knows nothing about get the generic account header.
+    let header = document.find(Class("header-body")).find(|x| {
+        x.find(Class("header-pretitle"))
+            .next()
+            .is_some_and(|x| matches!(x.text().trim(), "Token" | "Metaplex NFT"))
+    });
+    let header = match header {
+        Some(header) => header,
+        None => return (None, None),
+    };
+    let known = |x: Node| {
+        let text = x.text().trim().to_string();
+        let placeholder = matches!(
+            text.as_str(),
+            "" | "Unknown Token" | "No NFT name was found" | "No Symbol was found"
+        );
+        (!placeholder).then_some(text)
+    };
+
+    let name = header.find(Class("header-title")).next().and_then(known);
+    let symbol = header.find(Class("header-pretitle")).nth(1).and_then(known);
+    (name, symbol)
+}
+
+fn parse_token_account(rows: &Rows) -> Result<TokenAccount, ScrapeError> {
+    let mint = rows.link("Mint")?;
+    let owner = rows.link("Owner")?;
+    let balance = rows.text("Token balance")?;
+    let status = rows.has("Status").then(|| rows.text("Status"));
+
+    Ok(TokenAccount {
+        mint: mint.pubkey,
+        mint_label: mint.label,
+        owner: owner.pubkey,
+        owner_label: owner.label,
+        balance: TokenAmount::parse(&balance, "token balance")?,
+        status: status.transpose()?,
+    })
+}
+
+/// Stake accounts spread their details over the overview and the Stake
+/// Delegation and Authorities cards.
+fn parse_stake_account(document: &Document, rows: &Rows) -> Result<StakeAccount, ScrapeError> {
+    let delegation =
+        titled_card(document, "Stake Delegation").map(|card| Rows::of(&card, "Stake Delegation"));
+    let authorities = titled_card(document, "Authorities").or_missing("Authorities", ".card")?;
+    let authorities = Rows::of(&authorities, "Authorities");
+
+    // The status moves to the overview when there's no delegation to show.
+    let status = match &delegation {
+        Some(delegation) if !rows.has("Status") => delegation.text("Status")?,
+        _ => rows.text("Status")?,
+    };
+    let delegation = match delegation {
+        Some(delegation) if delegation.has("Delegated Vote Address") => Some(StakeDelegation {
+            voter: delegation.link("Delegated Vote Address")?.pubkey,
+            delegated_stake: delegation.balance("Delegated Stake")?,
+            active_stake: delegation.optional_balance("Active Stake")?,
+            inactive_stake: delegation.optional_balance("Inactive Stake")?,
+            activation_epoch: delegation.number_link("Activation Epoch")?,
+            deactivation_epoch: delegation.number_link("Deactivation Epoch")?,
+        }),
+        _ => None,
+    };
+    let lockup = if authorities.has("Lockup Expiry Epoch") {
+        let expiry_timestamp = parse_timestamp(
+            &authorities.text("Lockup Expiry Timestamp")?,
+            &authorities.field("Lockup Expiry Timestamp"),
+        )?;
+        Some(Lockup {
+            expiry_epoch: authorities
+                .number_link("Lockup Expiry Epoch")?
+                .or_missing(&authorities.field("Lockup Expiry Epoch"), "a")?,
+            expiry_timestamp,
+            expiry_unix_timestamp: expiry_timestamp.timestamp(),
+            custodian: authorities.link("Lockup Custodian")?.pubkey,
+        })
+    } else {
+        None
+    };
+
+    Ok(StakeAccount {
+        balance: rows.balance("Balance")?,
+        rent_reserve: rows.balance("Rent Reserve")?,
+        status,
+        stake_authority: authorities.link("Stake Authority")?.pubkey,
+        withdraw_authority: authorities.link("Withdraw Authority")?.pubkey,
+        delegation,
+        lockup,
+    })
+}
+
+fn parse_vote_account(rows: &Rows) -> Result<VoteAccount, ScrapeError> {
+    let last_timestamp =
+        parse_timestamp(&rows.text("Last Timestamp")?, &rows.field("Last Timestamp"))?;
+    let commission = rows.text("Commission")?;
+
+    Ok(VoteAccount {
+        balance: rows.balance("Balance")?,
+        authorized_voters: rows
+            .links("Authorized Voter")?
+            .into_iter()
+            .map(|x| x.pubkey)
+            .collect(),
+        authorized_withdrawer: rows.link("Authorized Withdrawer")?.pubkey,
+        last_timestamp,
+        last_unix_timestamp: last_timestamp.timestamp(),
+        commission: parse_number(commission.trim_end_matches('%'), "commission")?,
+        root_slot: rows.number_link("Root Slot")?,
+    })
+}
+
+fn parse_nonce_account(rows: &Rows) -> Result<NonceAccount, ScrapeError> {
+    let fee = rows.text("Fee")?;
+    let fee = fee.trim_end_matches("lamports per signature");
+
+    Ok(NonceAccount {
+        balance: rows.balance("Balance")?,
+        authority: rows.link("Authority")?.pubkey,
+        blockhash: rows.text("Blockhash")?,
+        fee_per_signature: Lamports(parse_number(fee, "fee")?),
+    })
+}
+
+fn parse_lookup_table(rows: &Rows) -> Result<LookupTableAccount, ScrapeError> {
+    let status = rows.text("Activation Status")?;
+    let active = match status.to_ascii_lowercase().as_str() {
+        "active" => true,
+        "deactivated" => false,
+        other => {
+            return Err(ScrapeError::layout(
+                "Address Lookup Table Account: Activation Status",
+                format!("expected active or deactivated, found {:?}", other),
+            ))
+        }
     };
 
-    let mut list = document.find(Class("list"));
-    _ = list.next();
-    let transaction_nodes = list.next().unwrap().children();
+    Ok(LookupTableAccount {
+        balance: rows.balance("Balance")?,
+        active,
+        last_extended_slot: rows.number_link("Last Extended Slot")?,
+        authority: rows.optional_link("Authority")?.map(|x| x.pubkey),
+    })
+}
+
+fn parse_data_size(size: &str) -> Result<u64, ScrapeError> {
+    parse_number(size.trim_end_matches("byte(s)"), "data_size")
+}
+
+/// The rows of an overview card, looked up by their label since they differ
+/// between kinds of account.
+struct Rows<'a> {
+    section: String,
+    rows: Vec<(String, Node<'a>)>,
+}
+
+impl<'a> Rows<'a> {
+    fn of(card: &Node<'a>, section: &str) -> Self {
+        let rows = card
+            .find(Class("list").descendant(Name("tr")))
+            .filter_map(|row| {
+                let label = row.find(Name("td")).next()?.text();
+                Some((label.trim().to_string(), row))
+            })
+            .collect();
+        Rows {
+            section: section.to_string(),
+            rows,
+        }
+    }
+
+    fn has(&self, label: &str) -> bool {
+        self.rows.iter().any(|x| x.0.starts_with(label))
+    }
+
+    /// The value cell of the first row whose label starts with `label`.
+    fn value(&self, label: &str) -> Result<Node<'a>, ScrapeError> {
+        self.rows
+            .iter()
+            .find(|x| x.0.starts_with(label))
+            .or_missing(&self.section, &format!("{} row", label))?
+            .1
+            .find(Name("td"))
+            .nth(1)
+            .or_missing(&self.field(label), "td")
+    }
+
+    fn field(&self, label: &str) -> String {
+        format!("{}: {}", self.section, label)
+    }
+
+    fn text(&self, label: &str) -> Result<String, ScrapeError> {
+        Ok(self.value(label)?.text().trim().to_string())
+    }
+
+    fn balance(&self, label: &str) -> Result<Lamports, ScrapeError> {
+        let balance = self
+            .value(label)?
+            .find(Class("font-monospace"))
+            .next()
+            .or_missing(&self.field(label), ".font-monospace")?
+            .text();
+        Lamports::parse(&balance, &label.to_ascii_lowercase())
+    }
+
+    fn optional_balance(&self, label: &str) -> Result<Option<Lamports>, ScrapeError> {
+        if !self.has(label) {
+            return Ok(None);
+        }
+        Ok(Some(self.balance(label)?))
+    }
+
+    fn link(&self, label: &str) -> Result<AddressLink, ScrapeError> {
+        self.optional_link(label)?
+            .or_missing(&self.field(label), "a")
+    }
+
+    /// A link, or `None` when the cell shows some text such as `None` instead.
+    fn optional_link(&self, label: &str) -> Result<Option<AddressLink>, ScrapeError> {
+        Ok(self.links(label)?.into_iter().next())
+    }
+
+    fn links(&self, label: &str) -> Result<Vec<AddressLink>, ScrapeError> {
+        self.value(label)?
+            .find(Name("a"))
+            .filter(AddressLink::is_address_link)
+            .map(|x| AddressLink::parse(&x, &self.field(label)))
+            .collect()
+    }
+
+    /// The link of a row the explorer leaves out when there's nothing to link.
+    fn optional_row_link(&self, label: &str) -> Result<Option<String>, ScrapeError> {
+        if !self.has(label) {
+            return Ok(None);
+        }
+        Ok(Some(self.link(label)?.pubkey))
+    }
+
+    fn yes_no(&self, label: &str) -> Result<bool, ScrapeError> {
+        match self.text(label)?.to_ascii_lowercase().as_str() {
+            "no" => Ok(false),
+            "yes" => Ok(true),
+            other => Err(ScrapeError::layout(
+                self.field(label),
+                format!("expected yes or no, found {:?}", other),
+            )),
+        }
+    }
+
+    /// A slot or epoch link, or `None` when the cell shows some text instead.
+    fn number_link(&self, label: &str) -> Result<Option<u64>, ScrapeError> {
+        self.value(label)?
+            .find(Name("a"))
+            .next()
+            .map(|x| parse_number(&x.text(), &label.to_ascii_lowercase()))
+            .transpose()
+    }
+}
+
+/// Parses the history rows loaded so far, stopping at the edges of `range`.
+/// Also returns whether one of those edges was reached.
+fn parse_history(
+    document: &Document,
+    range: &HistoryRange,
+) -> Result<(Vec<Transaction>, bool), ScrapeError> {
     let mut transactions: Vec<Transaction> = vec![];
+    if history_list(document).is_none() {
+        if has_no_history(document) {
+            log::info!("Account has no transaction history");
+            return Ok((transactions, true));
+        }
+        return Err(ScrapeError::missing("Transaction History", ".list"));
+    }
 
-    for transaction in transaction_nodes {
-        if transactions.len() == txns_limit {
-            break;
+    for (index, transaction) in history_rows(document).enumerate() {
+        if transactions.len() >= range.limit {
+            return Ok((transactions, true));
         }
+        let section = format!("Transaction History row {}", index + 1);
         let mut details = transaction.find(Name("td"));
         let signature = details
             .next()
-            .unwrap()
+            .or_missing(&section, "td")?
             .find(Name("a"))
             .next()
-            .unwrap()
+            .or_missing(&section, "td a")?
             .text();
-        let block = details.next().unwrap().find(Name("a")).next().unwrap();
-        let block: u64 = block.text().split(',').collect::<String>().parse().unwrap();
-        let timestamp = details.next().unwrap().find(Name("time")).next().unwrap();
+        let block = details
+            .next()
+            .or_missing(&section, "td")?
+            .find(Name("a"))
+            .next()
+            .or_missing(&section, "td a")?;
+        let block: u64 = parse_number(&block.text(), "block")?;
+        let timestamp = details
+            .next()
+            .or_missing(&section, "td")?
+            .find(Name("time"))
+            .next()
+            .or_missing(&section, "td time")?;
         let _ = details.next();
-        let result = details.next().unwrap().first_child().unwrap().text();
+        let result = details
+            .next()
+            .or_missing(&section, "td")?
+            .first_child()
+            .or_missing(&section, "td > *")?
+            .text();
 
         let time = {
-            let timestamp: i64 = timestamp.attr("datetime").unwrap().parse().unwrap();
-            let utc = DateTime::<Utc>::from_utc(
-                NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap(),
-                Utc,
-            );
-            format!("{}", utc.format("%Y-%m-%d %H:%M:%S"))
+            let datetime = timestamp
+                .attr("datetime")
+                .or_missing(&section, "time[datetime]")?;
+            let timestamp: i64 = parse_number(datetime, "time")?;
+            NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                .map(|x| DateTime::<Utc>::from_utc(x, Utc))
+                .ok_or_else(|| ScrapeError::UnparsableNumber {
+                    field: "time".to_string(),
+                    raw: datetime.to_string(),
+                })?
         };
 
+        if range.until.as_deref() == Some(signature.as_str())
+            || range.since.is_some_and(|since| time < since)
+        {
+            return Ok((transactions, true));
+        }
+
         let new_transaction = Transaction {
             signature,
             block,
             time,
+            unix_timestamp: time.timestamp(),
             result,
         };
 
         transactions.push(new_transaction);
     }
 
-    let details = AccountDetails {
-        address,
-        balance,
-        owner,
-        data_size,
-        executable,
-        recent_transactions: transactions,
-    };
+    let complete = transactions.len() >= range.limit;
+    Ok((transactions, complete))
+}
 
-    Ok(details)
+/// The list of the Transaction History card. Some kinds of account show more
+/// than one card before it.
+fn history_list(document: &Document) -> Option<Node<'_>> {
+    titled_card(document, "Transaction History")?
+        .find(Class("list"))
+        .next()
+}
+
+fn history_rows(document: &Document) -> impl Iterator<Item = Node<'_>> {
+    history_list(document)
+        .into_iter()
+        .flat_map(|list| list.children().filter(|x| x.is(Name("tr"))))
+}
+
+/// The button that loads older transactions, the same one [`can_load_more`]
+/// looks for. Other cards can have buttons in their footer too.
+const LOAD_MORE_BUTTON: &str = "//*[contains(concat(' ', @class, ' '), ' card-footer ')]\
+    //*[contains(concat(' ', @class, ' '), ' btn ')][normalize-space() = 'Load More']";
+
+/// Whether the history card offers to load older transactions.
+fn can_load_more(document: &Document) -> bool {
+    document
+        .find(Class("card-footer").descendant(Class("btn")))
+        .any(|x| x.text().trim() == "Load More")
+}
+
+fn has_no_history(document: &Document) -> bool {
+    document
+        .find(Class("card-body"))
+        .any(|x| x.text().contains("No transactions found"))
 }
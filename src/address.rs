@@ -0,0 +1,99 @@
+use crate::error::{OrMissing, ScrapeError};
+use select::node::Node;
+
+/// An address link as rendered by the explorer: the pubkey it points to, and
+/// the name shown in its place when the explorer knows one.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AddressLink {
+    pub pubkey: String,
+    pub label: Option<String>,
+}
+
+impl AddressLink {
+    /// Reads an `<a href="/address/<pubkey>">` link.
+    pub fn parse(link: &Node, section: &str) -> Result<Self, ScrapeError> {
+        let href = link.attr("href").or_missing(section, "a[href]")?;
+        let pubkey = pubkey_from_href(href).ok_or_else(|| {
+            ScrapeError::layout(section, format!("not an address link: {:?}", href))
+        })?;
+
+        let text = link.text();
+        let text = text.trim();
+        let label = if text == pubkey || text == format!("Unknown Program ({})", pubkey) {
+            None
+        } else {
+            Some(text.to_string())
+        };
+
+        Ok(AddressLink {
+            pubkey: pubkey.to_string(),
+            label,
+        })
+    }
+
+    /// Whether `link` points to an address page.
+    pub fn is_address_link(link: &Node) -> bool {
+        link.attr("href").and_then(pubkey_from_href).is_some()
+    }
+}
+
+/// Takes the pubkey out of an explorer path such as `/address/<pubkey>/tokens?cluster=devnet`.
+fn pubkey_from_href(href: &str) -> Option<&str> {
+    let path = href.split(['?', '#']).next()?;
+    let (_, rest) = path.split_once("/address/")?;
+    let pubkey = rest.split('/').next()?;
+    is_pubkey(pubkey).then_some(pubkey)
+}
+
+/// Whether `pubkey` is a base58 encoded 32 byte key.
+pub fn is_pubkey(pubkey: &str) -> bool {
+    (32..=44).contains(&pubkey.len())
+        && bs58::decode(pubkey)
+            .into_vec()
+            .is_ok_and(|bytes| bytes.len() == 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use select::{document::Document, predicate::Name};
+
+    fn link(html: &str) -> Result<AddressLink, ScrapeError> {
+        let document = Document::from(html);
+        let link = document.find(Name("a")).next().unwrap();
+        AddressLink::parse(&link, "test")
+    }
+
+    #[test]
+    fn separates_pubkeys_from_labels() {
+        assert_eq!(
+            link(r#"<a href="/address/11111111111111111111111111111111?cluster=devnet">System Program</a>"#)
+                .unwrap(),
+            AddressLink {
+                pubkey: "11111111111111111111111111111111".to_string(),
+                label: Some("System Program".to_string()),
+            }
+        );
+        assert_eq!(
+            link(r#"<a href="/address/sunzv8N3A8dRHwUBvxgRDEbWKk8t7yiHR4FLRgFsTX6">Unknown Program (sunzv8N3A8dRHwUBvxgRDEbWKk8t7yiHR4FLRgFsTX6)</a>"#)
+                .unwrap()
+                .label,
+            None
+        );
+    }
+
+    #[test]
+    fn decodes_pubkeys() {
+        assert!(is_pubkey("11111111111111111111111111111111"));
+        assert!(is_pubkey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"));
+        // Base58 text of the right length that doesn't decode to 32 bytes.
+        assert!(!is_pubkey("HappyBirthdayToYouMyFriendsAndCoworkers"));
+        assert!(!is_pubkey("zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz"));
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        assert!(link(r#"<a href="/block/199512007?cluster=devnet">199,512,007</a>"#).is_err());
+        assert!(link(r#"<a href="/address/not-a-key">label</a>"#).is_err());
+    }
+}
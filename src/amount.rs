@@ -0,0 +1,230 @@
+use crate::error::{strip_separators, ScrapeError};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::fmt;
+
+/// Number of decimal places of SOL, i.e. lamports per SOL is `10^9`.
+pub const SOL_DECIMALS: u8 = 9;
+
+/// An exact SOL balance in lamports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lamports(pub u64);
+
+/// An exact, signed change of a SOL balance in lamports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LamportsChange(pub i64);
+
+/// An exact token amount: `raw` units of `10^-decimals` tokens.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Amount<T> {
+    pub raw: T,
+    pub decimals: u8,
+}
+
+pub type TokenAmount = Amount<u64>;
+pub type TokenChange = Amount<i128>;
+
+impl Lamports {
+    /// Parses a SOL amount as displayed by the explorer, e.g. `9,515.234990246`.
+    pub fn parse(raw: &str, field: &str) -> Result<Self, ScrapeError> {
+        let lamports = parse_decimal(raw, field)?.scaled(SOL_DECIMALS, raw, field)?;
+        u64::try_from(lamports)
+            .map(Lamports)
+            .map_err(|_| unparsable(raw, field))
+    }
+}
+
+impl LamportsChange {
+    pub fn parse(raw: &str, field: &str) -> Result<Self, ScrapeError> {
+        let lamports = parse_decimal(raw, field)?.scaled(SOL_DECIMALS, raw, field)?;
+        i64::try_from(lamports)
+            .map(LamportsChange)
+            .map_err(|_| unparsable(raw, field))
+    }
+}
+
+impl<T> Amount<T>
+where
+    T: TryFrom<i128> + Into<i128> + Copy,
+{
+    /// Parses a token amount as displayed, keeping as many decimals as are shown.
+    pub fn parse(raw: &str, field: &str) -> Result<Self, ScrapeError> {
+        let amount = parse_decimal(raw, field)?;
+        Ok(Amount {
+            raw: T::try_from(amount.raw).map_err(|_| unparsable(raw, field))?,
+            decimals: amount.decimals,
+        })
+    }
+
+    /// The same amount expressed with `decimals` decimal places, or `None` if
+    /// it has more decimals than that or doesn't fit.
+    pub fn with_decimals(self, decimals: u8) -> Option<Self> {
+        let raw = Amount {
+            raw: self.raw.into(),
+            decimals: self.decimals,
+        }
+        .scale_to(decimals)?;
+        Some(Amount {
+            raw: T::try_from(raw).ok()?,
+            decimals,
+        })
+    }
+}
+
+impl Amount<i128> {
+    fn scale_to(self, decimals: u8) -> Option<i128> {
+        let shift = decimals.checked_sub(self.decimals)?;
+        self.raw.checked_mul(10i128.checked_pow(shift as u32)?)
+    }
+
+    fn scaled(self, decimals: u8, raw: &str, field: &str) -> Result<i128, ScrapeError> {
+        self.scale_to(decimals)
+            .ok_or_else(|| unparsable(raw, field))
+    }
+}
+
+/// Parses a decimal number as displayed by the explorer.
+fn parse_decimal(raw: &str, field: &str) -> Result<Amount<i128>, ScrapeError> {
+    let digits = strip_separators(raw);
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    let valid = |x: &str| x.chars().all(|x| x.is_ascii_digit());
+    if whole.is_empty() || !valid(whole) || !valid(fraction) {
+        return Err(unparsable(raw, field));
+    }
+    let decimals = u8::try_from(fraction.len()).map_err(|_| unparsable(raw, field))?;
+    let amount: i128 = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| unparsable(raw, field))?;
+
+    Ok(Amount {
+        raw: if negative { -amount } else { amount },
+        decimals,
+    })
+}
+
+fn unparsable(raw: &str, field: &str) -> ScrapeError {
+    ScrapeError::UnparsableNumber {
+        field: field.to_string(),
+        raw: raw.to_string(),
+    }
+}
+
+/// Renders `raw * 10^-decimals` without trailing zeros.
+fn format_decimal(raw: i128, decimals: u8) -> String {
+    let sign = if raw < 0 { "-" } else { "" };
+    let digits = format!(
+        "{:0>width$}",
+        raw.unsigned_abs(),
+        width = decimals as usize + 1
+    );
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+impl fmt::Display for Lamports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_decimal(self.0.into(), SOL_DECIMALS))
+    }
+}
+
+impl fmt::Display for LamportsChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_decimal(self.0.into(), SOL_DECIMALS))
+    }
+}
+
+impl<T: Into<i128> + Copy> fmt::Display for Amount<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_decimal(self.raw.into(), self.decimals))
+    }
+}
+
+impl Serialize for Lamports {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Lamports", 2)?;
+        state.serialize_field("lamports", &self.0)?;
+        state.serialize_field("sol", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl Serialize for LamportsChange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("LamportsChange", 2)?;
+        state.serialize_field("lamports", &self.0)?;
+        state.serialize_field("sol", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// `raw` is written as a string, like token amounts in the JSON RPC API, so
+/// it survives JSON parsers that read numbers as doubles.
+impl<T: Into<i128> + Copy> Serialize for Amount<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Amount", 3)?;
+        state.serialize_field("raw", &self.raw.into().to_string())?;
+        state.serialize_field("decimals", &self.decimals)?;
+        state.serialize_field("amount", &self.to_string())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sol_as_lamports() {
+        assert_eq!(
+            Lamports::parse("9,515.234990246", "balance").unwrap(),
+            Lamports(9_515_234_990_246)
+        );
+        assert_eq!(Lamports::parse("0.000005", "fee").unwrap(), Lamports(5000));
+        assert_eq!(
+            Lamports::parse("1\u{a0}204.5", "balance").unwrap(),
+            Lamports(1_204_500_000_000)
+        );
+        assert_eq!(
+            LamportsChange::parse("-1.999999999", "sol_change").unwrap(),
+            LamportsChange(-1_999_999_999)
+        );
+        assert!(Lamports::parse("0.0000000001", "balance").is_err());
+        assert!(Lamports::parse("-1", "balance").is_err());
+        assert!(Lamports::parse("1.5 SOL", "balance").is_err());
+    }
+
+    #[test]
+    fn keeps_token_amounts_exact() {
+        let amount = TokenAmount::parse("18,446,744,073.709551615", "post_balance").unwrap();
+        assert_eq!(
+            amount,
+            Amount {
+                raw: u64::MAX,
+                decimals: 9
+            }
+        );
+        assert_eq!(amount.to_string(), "18446744073.709551615");
+
+        let change = TokenChange::parse("-1.5", "change").unwrap();
+        assert_eq!(change.with_decimals(6).unwrap().raw, -1_500_000);
+        assert_eq!(change.with_decimals(0), None);
+    }
+
+    #[test]
+    fn renders_without_trailing_zeros() {
+        assert_eq!(Lamports(15_001_461_600).to_string(), "15.0014616");
+        assert_eq!(Lamports(1).to_string(), "0.000000001");
+        assert_eq!(LamportsChange(-5000).to_string(), "-0.000005");
+        assert_eq!(Lamports(0).to_string(), "0");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::ScrapeError;
+use serde::Serialize;
+use std::io::{self, BufRead};
+
+/// Header names skipped when they appear in the first column of the first line.
+const HEADERS: [&str; 4] = ["id", "signature", "address", "account"];
+
+#[derive(Debug, Serialize)]
+pub struct BatchReport<T> {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItem<T>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItem<T> {
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: Outcome<T>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome<T> {
+    Result(T),
+    Error(String),
+}
+
+impl<T> BatchReport<T> {
+    pub fn new() -> Self {
+        BatchReport {
+            succeeded: 0,
+            failed: 0,
+            items: vec![],
+        }
+    }
+
+    pub fn push(&mut self, id: String, result: Result<T, ScrapeError>) {
+        let outcome = match result {
+            Ok(result) => {
+                self.succeeded += 1;
+                Outcome::Result(result)
+            }
+            Err(err) => {
+                log::warn!("Failed scraping {}: {}", id, err);
+                self.failed += 1;
+                Outcome::Error(err.to_string())
+            }
+        };
+        self.items.push(BatchItem { id, outcome });
+    }
+}
+
+impl<T> Default for BatchReport<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads ids one per line, or from the first column of a CSV file.
+/// Blank lines, `#` comments and a header row are skipped.
+pub fn read_ids(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut ids = vec![];
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let id = line
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"');
+        if ids.is_empty() && HEADERS.contains(&id.to_lowercase().as_str()) {
+            continue;
+        }
+        if !id.is_empty() {
+            ids.push(id.to_string());
+        }
+    }
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_plain_lines() {
+        let input = "5j7s6NiJS3JAkvgkoc18WVAsiSaci2pxB2A6ueCJP4tprA2TFg9wSyTLeYouxPBJEMzJinENTkpA52YStRW5Dia7\n\n# skipped\n  MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr  \n";
+        let ids = read_ids(input.as_bytes()).unwrap();
+
+        assert_eq!(
+            ids,
+            [
+                "5j7s6NiJS3JAkvgkoc18WVAsiSaci2pxB2A6ueCJP4tprA2TFg9wSyTLeYouxPBJEMzJinENTkpA52YStRW5Dia7",
+                "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_first_csv_column() {
+        let input = "signature,note\n\"4XQZckrFKjaLHM68kJH7dpSPo2TCfMkwjYhLdcNRu5QdJTjAEehsS5UMaZKDXADD46d8v4XnuyuvLV36rNRTKhn7\",deposit\n3yGw6WMAbHo6bt4vVw4FzqLzRrWUvKfwbXTumyUHBq5U5YCqrsVgY3ufVCXiJnJsDzKBhRQCHrFK4bcPnwMQpjKm,withdraw\n";
+        let ids = read_ids(input.as_bytes()).unwrap();
+
+        assert_eq!(
+            ids,
+            [
+                "4XQZckrFKjaLHM68kJH7dpSPo2TCfMkwjYhLdcNRu5QdJTjAEehsS5UMaZKDXADD46d8v4XnuyuvLV36rNRTKhn7",
+                "3yGw6WMAbHo6bt4vVw4FzqLzRrWUvKfwbXTumyUHBq5U5YCqrsVgY3ufVCXiJnJsDzKBhRQCHrFK4bcPnwMQpjKm",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_failures_next_to_results() {
+        let mut report = BatchReport::new();
+        report.push("a".to_string(), Ok(1));
+        report.push(
+            "b".to_string(),
+            Err(ScrapeError::NotFound {
+                message: "Not Found".to_string(),
+            }),
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "succeeded": 1,
+                "failed": 1,
+                "items": [
+                    { "id": "a", "result": 1 },
+                    { "id": "b", "error": "explorer couldn't find the page: Not Found" },
+                ]
+            })
+        );
+    }
+}
@@ -1,33 +1,375 @@
-use crate::Error;
-use clap::Parser;
+use crate::{page::Wait, Error};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
 use fantoccini::{Client, ClientBuilder};
 use serde::Deserialize;
-use serde_json::map::Map;
+use serde_json::{json, map::Map, Value};
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
 use tokio::sync::Mutex;
+use url::Url;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about=None)]
 pub struct Args {
-    #[clap(short, long, help = "account|transaction")]
-    pub parse: String,
+    #[clap(subcommand)]
+    pub command: Commands,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Scrape an account's details and recent transactions
+    Account {
+        #[clap(
+            required_unless_present_any = ["html_file", "input_file"],
+            conflicts_with = "input_file",
+            help = "Address of the account to scrape"
+        )]
+        address: Option<String>,
+
+        #[clap(long, help = "Number of recent transactions to include")]
+        tx_limit: Option<u64>,
+
+        #[clap(
+            long,
+            value_name = "SIGNATURE",
+            help = "Stop the history at this transaction, excluding it"
+        )]
+        until: Option<String>,
+
+        #[clap(
+            long,
+            value_name = "DATE",
+            value_parser = parse_date,
+            help = "Stop the history at transactions older than this date (YYYY-MM-DD or RFC 3339)"
+        )]
+        since: Option<DateTime<Utc>>,
+
+        #[clap(
+            long,
+            help = "Leave token accounts with a zero balance out of the token holdings"
+        )]
+        hide_zero_balances: bool,
+
+        #[clap(flatten)]
+        options: ScrapeOptions,
+    },
+    /// Scrape a transaction's details, instructions and logs
+    #[clap(alias = "transaction")]
+    Tx {
+        #[clap(
+            required_unless_present_any = ["html_file", "input_file"],
+            conflicts_with = "input_file",
+            help = "Signature of the transaction to scrape"
+        )]
+        signature: Option<String>,
 
-    #[clap(short, long, help = "Id of the account|tx to be parsed")]
-    pub id: String,
+        #[clap(flatten)]
+        options: ScrapeOptions,
+    },
+}
+
+#[derive(clap::Args)]
+pub struct ScrapeOptions {
+    #[clap(
+        short,
+        long,
+        help = "Overrides the cluster in config.yml: mainnet, devnet, testnet or an RPC url"
+    )]
+    pub cluster: Option<Cluster>,
+
+    #[clap(long, value_parser = parse_url, help = "Overrides explorer_url in config.yml")]
+    pub explorer_url: Option<Url>,
+
+    #[clap(short, long, help = "Overrides output_file_path in config.yml")]
+    pub output: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        value_enum,
+        help = "Overrides output_format in config.yml"
+    )]
+    pub format: Option<Format>,
+
+    #[clap(
+        long,
+        help = "Parse a saved explorer page instead of connecting to chromedriver"
+    )]
+    pub html_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "html_file",
+        help = "Scrape every id in a file, one per line or CSV first column. Use - for stdin"
+    )]
+    pub input_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["html_file", "input_file"],
+        help = "Save the rendered explorer page to this file instead of scraping it"
+    )]
+    pub save_html: Option<PathBuf>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
-    pub cluster: String,
+    pub cluster: Cluster,
+    #[serde(default = "default_explorer_url", deserialize_with = "deserialize_url")]
+    pub explorer_url: Url,
     pub wait_time: u64,
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
     pub tx_limit: u64,
     pub output_file_path: String,
+    #[serde(default)]
+    pub output_format: Format,
+    #[serde(default)]
+    pub webdriver: WebDriverConfig,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WebDriverConfig {
+    pub url: String,
+    pub browser: Browser,
+    pub args: Vec<String>,
+    pub window_size: Option<WindowSize>,
+    pub user_agent: Option<String>,
+    /// `host:port` of a proxy used for both http and https traffic.
+    pub proxy: Option<String>,
+    pub no_proxy: Vec<String>,
+}
+
+impl Default for WebDriverConfig {
+    fn default() -> Self {
+        WebDriverConfig {
+            url: "http://localhost:4444".to_string(),
+            browser: Browser::Chrome,
+            args: vec!["--headless".to_string(), "--disable-gpu".to_string()],
+            window_size: None,
+            user_agent: None,
+            proxy: None,
+            no_proxy: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Browser {
+    Chrome,
+    Firefox,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WebDriverConfig {
+    /// Builds the capabilities requested from the webdriver server for this browser.
+    ///
+    /// The browser is pinned to the en-US locale, and Firefox to UTC, so pages
+    /// render the same on every machine. Chrome has no such option, see
+    /// [`new_webdriver_client`].
+    pub fn capabilities(&self) -> Map<String, Value> {
+        let mut caps = Map::new();
+        match self.browser {
+            Browser::Chrome => {
+                let mut args = self.args.clone();
+                args.push(format!("--lang={}", LOCALE));
+                if let Some(user_agent) = &self.user_agent {
+                    args.push(format!("--user-agent={}", user_agent));
+                }
+                caps.insert("browserName".to_string(), json!("chrome"));
+                caps.insert(
+                    "goog:chromeOptions".to_string(),
+                    json!({
+                        "args": args,
+                        "prefs": { "intl.accept_languages": LOCALE },
+                    }),
+                );
+            }
+            Browser::Firefox => {
+                let mut prefs = json!({
+                    "intl.locale.requested": LOCALE,
+                    "intl.accept_languages": LOCALE,
+                });
+                if let Some(user_agent) = &self.user_agent {
+                    prefs["general.useragent.override"] = json!(user_agent);
+                }
+                caps.insert("browserName".to_string(), json!("firefox"));
+                caps.insert(
+                    "moz:firefoxOptions".to_string(),
+                    json!({
+                        "args": self.args,
+                        "env": { "TZ": TIMEZONE },
+                        "prefs": prefs,
+                    }),
+                );
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            caps.insert(
+                "proxy".to_string(),
+                json!({
+                    "proxyType": "manual",
+                    "httpProxy": proxy,
+                    "sslProxy": proxy,
+                    "noProxy": self.no_proxy,
+                }),
+            );
+        }
+        caps
+    }
+}
+
+impl Config {
+    /// Applies the options given on the command line over the values from config.yml.
+    pub fn override_with(&mut self, options: &ScrapeOptions) {
+        if let Some(output) = &options.output {
+            self.output_file_path = output.clone();
+        }
+        if let Some(format) = options.format {
+            self.output_format = format;
+        }
+        if let Some(cluster) = &options.cluster {
+            self.cluster = cluster.clone();
+        }
+        if let Some(explorer_url) = &options.explorer_url {
+            self.explorer_url = explorer_url.clone();
+        }
+    }
+
+    /// Waits up to `wait_time` seconds for a page, checking every `poll_interval` milliseconds.
+    pub fn page_wait(&self) -> Wait {
+        Wait {
+            timeout: Duration::from_secs(self.wait_time),
+            interval: Duration::from_millis(self.poll_interval),
+        }
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    250
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_explorer_url() -> Url {
+    Url::parse("https://explorer.solana.com").expect("valid default explorer url")
+}
+
+fn deserialize_url<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Url, D::Error> {
+    let url = String::deserialize(deserializer)?;
+    parse_url(&url).map_err(serde::de::Error::custom)
+}
+
+fn parse_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url.trim()).map_err(|err| format!("invalid url {:?}: {}", url, err))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(format!("unsupported url scheme {:?} in {}", scheme, url)),
+    }
+}
+
+/// Parses an RFC 3339 timestamp, or a date taken as midnight UTC.
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|x| x.and_hms_opt(0, 0, 0))
+        .map(|x| DateTime::<Utc>::from_utc(x, Utc))
+        .ok_or_else(|| format!("invalid date {:?}, expected YYYY-MM-DD or RFC 3339", date))
 }
 
-#[derive(Debug)]
+/// The cluster the explorer shows. `Custom` points it at any RPC endpoint,
+/// e.g. a local validator.
+///
+/// In config.yml this is either a name or an RPC url:
+///
+/// ```yaml
+/// cluster: testnet
+/// cluster:
+///   custom: http://localhost:8899
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ClusterConfig")]
 pub enum Cluster {
-    Devnet,
     Mainnet,
+    Devnet,
     Testnet,
+    Custom(Url),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClusterConfig {
+    Name(String),
+    Custom { custom: String },
+}
+
+impl TryFrom<ClusterConfig> for Cluster {
+    type Error = String;
+
+    fn try_from(config: ClusterConfig) -> Result<Self, Self::Error> {
+        match config {
+            ClusterConfig::Name(name) => name.parse(),
+            ClusterConfig::Custom { custom } => parse_url(&custom).map(Cluster::Custom),
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    /// Accepts a cluster name, `custom:<url>` or a bare http(s) RPC url.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "mainnet" | "mainnet-beta" => Ok(Cluster::Mainnet),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            lower if lower.starts_with("custom:") => {
+                parse_url(&s["custom:".len()..]).map(Cluster::Custom)
+            }
+            lower if lower.starts_with("http://") || lower.starts_with("https://") => {
+                parse_url(s).map(Cluster::Custom)
+            }
+            _ => Err(format!(
+                "unknown cluster {:?}, expected mainnet, devnet, testnet or an RPC url",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cluster::Mainnet => write!(f, "mainnet-beta"),
+            Cluster::Devnet => write!(f, "devnet"),
+            Cluster::Testnet => write!(f, "testnet"),
+            Cluster::Custom(url) => write!(f, "custom ({})", url),
+        }
+    }
 }
 
 pub enum Command {
@@ -35,32 +377,238 @@ pub enum Command {
     Transaction,
 }
 
-pub async fn new_webdriver_client() -> Result<Mutex<Client>, Error> {
-    let mut caps = Map::new();
-    let options = serde_json::json!({ "args": ["--headless", "--disable-gpu"] });
-    caps.insert("goog:chromeOptions".to_string(), options);
-    let webdriver_client = ClientBuilder::rustls()
-        .capabilities(caps)
-        .connect("http://localhost:4444")
+/// Timezone and locale every browser session renders pages in.
+const TIMEZONE: &str = "UTC";
+const LOCALE: &str = "en-US";
+
+pub async fn new_webdriver_client(config: &WebDriverConfig) -> Result<Mutex<Client>, Error> {
+    let mut webdriver_client = ClientBuilder::rustls()
+        .capabilities(config.capabilities())
+        .connect(&config.url)
         .await?;
+    if config.browser == Browser::Chrome {
+        override_chrome_timezone(config, &mut webdriver_client).await?;
+    }
+    if let Some(size) = config.window_size {
+        webdriver_client
+            .set_window_size(size.width, size.height)
+            .await?;
+    }
     log::info!("Webdriver client constructed!");
     Ok(Mutex::new(webdriver_client))
 }
 
-pub fn construct_url(cluster: &Cluster, command: &Command, id: &str) -> String {
+/// Sets the session's timezone through chromedriver's DevTools endpoint, which
+/// fantoccini has no command for.
+async fn override_chrome_timezone(
+    config: &WebDriverConfig,
+    webdriver_client: &mut Client,
+) -> Result<(), Error> {
+    let session = webdriver_client
+        .session_id()
+        .await?
+        .ok_or("webdriver session has no id")?;
+    let url = format!(
+        "{}/session/{}/goog/cdp/execute",
+        config.url.trim_end_matches('/'),
+        session
+    );
+    let body = json!({
+        "cmd": "Emulation.setTimezoneOverride",
+        "params": { "timezoneId": TIMEZONE },
+    });
+    let request = hyper::Request::post(url)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(body.to_string()))?;
+
+    // Same connector as the webdriver session, so https endpoints work too.
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    let response = hyper::Client::builder()
+        .build::<_, hyper::Body>(https)
+        .request(request)
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        return Err(format!(
+            "couldn't set the browser timezone to {}: {} {}",
+            TIMEZONE,
+            status,
+            String::from_utf8_lossy(&body)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Builds the explorer page for `id`. Mainnet is the explorer's default, so it
+/// gets no `cluster` query parameter.
+pub fn construct_url(explorer: &Url, cluster: &Cluster, command: &Command, id: &str) -> String {
     let route = match command {
         Command::Account => "address",
         Command::Transaction => "tx",
     };
+    let mut url = explorer.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    url.path_segments_mut()
+        .expect("explorer url is http(s)")
+        .pop_if_empty()
+        .extend([route, id]);
+
     match cluster {
-        Cluster::Mainnet => format!("https://explorer.solana.com/{}/{}", route, id),
-        Cluster::Devnet => format!(
-            "https://explorer.solana.com/{}/{}?cluster=devnet",
-            route, id
-        ),
-        Cluster::Testnet => format!(
-            "https://explorer.solana.com/{}/{}?cluster=mainnet-beta",
-            route, id
-        ),
+        Cluster::Mainnet => {}
+        Cluster::Devnet => {
+            url.query_pairs_mut().append_pair("cluster", "devnet");
+        }
+        Cluster::Testnet => {
+            url.query_pairs_mut().append_pair("cluster", "testnet");
+        }
+        Cluster::Custom(rpc) => {
+            url.query_pairs_mut()
+                .append_pair("cluster", "custom")
+                .append_pair("customUrl", rpc.as_str());
+        }
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_headless_chrome() {
+        let config = WebDriverConfig::default();
+
+        assert_eq!(
+            Value::Object(config.capabilities()),
+            json!({
+                "browserName": "chrome",
+                "goog:chromeOptions": {
+                    "args": ["--headless", "--disable-gpu", "--lang=en-US"],
+                    "prefs": { "intl.accept_languages": "en-US" },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn builds_firefox_capabilities() {
+        let config: WebDriverConfig = serde_yaml::from_str(
+            "
+browser: firefox
+args: [-headless]
+user_agent: dora/0.1
+proxy: proxy.internal:3128
+no_proxy: [localhost]
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Value::Object(config.capabilities()),
+            json!({
+                "browserName": "firefox",
+                "moz:firefoxOptions": {
+                    "args": ["-headless"],
+                    "env": { "TZ": "UTC" },
+                    "prefs": {
+                        "intl.locale.requested": "en-US",
+                        "intl.accept_languages": "en-US",
+                        "general.useragent.override": "dora/0.1",
+                    },
+                },
+                "proxy": {
+                    "proxyType": "manual",
+                    "httpProxy": "proxy.internal:3128",
+                    "sslProxy": "proxy.internal:3128",
+                    "noProxy": ["localhost"],
+                },
+            })
+        );
+    }
+
+    fn explorer() -> Url {
+        default_explorer_url()
+    }
+
+    #[test]
+    fn builds_urls_for_each_cluster() {
+        let id = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+        assert_eq!(
+            construct_url(&explorer(), &Cluster::Mainnet, &Command::Account, id),
+            "https://explorer.solana.com/address/MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
+        );
+        assert_eq!(
+            construct_url(&explorer(), &Cluster::Devnet, &Command::Transaction, id),
+            "https://explorer.solana.com/tx/MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr?cluster=devnet"
+        );
+        assert_eq!(
+            construct_url(&explorer(), &Cluster::Testnet, &Command::Account, id),
+            "https://explorer.solana.com/address/MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr?cluster=testnet"
+        );
+        assert_eq!(
+            construct_url(
+                &explorer(),
+                &"http://localhost:8899".parse().unwrap(),
+                &Command::Account,
+                id
+            ),
+            "https://explorer.solana.com/address/MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr?cluster=custom&customUrl=http%3A%2F%2Flocalhost%3A8899%2F"
+        );
+    }
+
+    #[test]
+    fn keeps_the_path_of_a_self_hosted_explorer() {
+        let explorer = Url::parse("https://tools.internal/explorer/").unwrap();
+
+        assert_eq!(
+            construct_url(&explorer, &Cluster::Devnet, &Command::Transaction, "abc"),
+            "https://tools.internal/explorer/tx/abc?cluster=devnet"
+        );
+    }
+
+    #[test]
+    fn takes_ids_from_the_command_line_or_a_file() {
+        let parse = |args: &[&str]| Args::try_parse_from([&["dora"], args].concat());
+
+        assert!(parse(&["account", "abc"]).is_ok());
+        assert!(parse(&["tx", "--input-file", "ids.txt"]).is_ok());
+        assert!(parse(&["account", "abc", "--input-file", "ids.txt"]).is_err());
+        assert!(parse(&["tx", "abc", "--input-file", "ids.txt"]).is_err());
+        assert!(parse(&["tx", "--html-file", "tx.html", "--input-file", "ids.txt"]).is_err());
+        assert!(parse(&["tx", "abc", "--save-html", "tx.html"]).is_ok());
+        assert!(parse(&["tx", "--input-file", "ids.txt", "--save-html", "tx.html"]).is_err());
+    }
+
+    #[test]
+    fn parses_history_dates() {
+        assert_eq!(
+            parse_date("2023-03-02").unwrap().to_rfc3339(),
+            "2023-03-02T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_date("2023-03-02T10:00:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2023-03-02T08:00:00+00:00"
+        );
+        assert!(parse_date("March 2nd").is_err());
+    }
+
+    #[test]
+    fn deserializes_clusters() {
+        let parse = |yaml: &str| serde_yaml::from_str::<Cluster>(yaml);
+
+        assert_eq!(parse("mainnet-beta").unwrap(), Cluster::Mainnet);
+        assert_eq!(parse("Testnet").unwrap(), Cluster::Testnet);
+        assert_eq!(
+            parse("custom: http://127.0.0.1:8899").unwrap(),
+            Cluster::Custom(Url::parse("http://127.0.0.1:8899").unwrap())
+        );
+        assert!(parse("localnet").is_err());
+        assert!(parse("custom: localhost:8899").is_err());
     }
 }
@@ -0,0 +1,89 @@
+use fantoccini::error::CmdError;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ScrapeError {
+    #[error("{section}: missing element `{selector}`")]
+    MissingElement { section: String, selector: String },
+
+    #[error("couldn't parse {field} from {raw:?}")]
+    UnparsableNumber { field: String, raw: String },
+
+    #[error("{section}: {reason}")]
+    UnexpectedLayout { section: String, reason: String },
+
+    #[error("explorer couldn't find the page: {message}")]
+    NotFound { message: String },
+
+    #[error("explorer failed to load the page: {message}")]
+    Explorer { message: String },
+
+    #[error("page didn't finish loading within {seconds}s")]
+    Timeout { seconds: u64 },
+
+    #[error("no webdriver session was available")]
+    NoSession,
+
+    #[error("webdriver command failed: {0}")]
+    WebDriver(#[from] CmdError),
+}
+
+impl ScrapeError {
+    pub fn missing(section: impl Into<String>, selector: impl Into<String>) -> Self {
+        ScrapeError::MissingElement {
+            section: section.into(),
+            selector: selector.into(),
+        }
+    }
+
+    pub fn layout(section: impl Into<String>, reason: impl Into<String>) -> Self {
+        ScrapeError::UnexpectedLayout {
+            section: section.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Whether the webdriver session behind this error is gone and needs replacing.
+    pub fn is_session_lost(&self) -> bool {
+        match self {
+            ScrapeError::WebDriver(CmdError::Lost(_))
+            | ScrapeError::WebDriver(CmdError::Failed(_))
+            | ScrapeError::WebDriver(CmdError::NoSuchWindow(_)) => true,
+            ScrapeError::WebDriver(CmdError::Standard(err)) => matches!(
+                err.error.error_code(),
+                "invalid session id" | "session not created"
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Turns a missing node or attribute into a [`ScrapeError::MissingElement`].
+pub trait OrMissing<T> {
+    fn or_missing(self, section: &str, selector: &str) -> Result<T, ScrapeError>;
+}
+
+impl<T> OrMissing<T> for Option<T> {
+    fn or_missing(self, section: &str, selector: &str) -> Result<T, ScrapeError> {
+        self.ok_or_else(|| ScrapeError::missing(section, selector))
+    }
+}
+
+/// Parses a number as displayed by the explorer.
+pub fn parse_number<T: FromStr>(raw: &str, field: &str) -> Result<T, ScrapeError> {
+    strip_separators(raw)
+        .parse()
+        .map_err(|_| ScrapeError::UnparsableNumber {
+            field: field.to_string(),
+            raw: raw.to_string(),
+        })
+}
+
+/// Drops the thousands separators of common locales from a displayed number.
+pub(crate) fn strip_separators(raw: &str) -> String {
+    raw.trim()
+        .chars()
+        .filter(|x| !matches!(x, ',' | ' ' | '\u{a0}' | '\u{202f}'))
+        .collect()
+}
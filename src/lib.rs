@@ -0,0 +1,11 @@
+pub mod account;
+pub mod address;
+pub mod amount;
+pub mod batch;
+pub mod config;
+pub mod error;
+pub mod page;
+pub mod pool;
+pub mod transaction;
+
+pub type Error = Box<dyn std::error::Error>;
@@ -1,12 +1,18 @@
 use clap::Parser;
-use config::{Args, Cluster, Command, Config};
-use std::{fs::File, io::Write};
-
-mod account;
-mod config;
-mod transaction;
-
-pub type Error = Box<dyn std::error::Error>;
+use dora::{
+    account::{self, HistoryRange, MintDecimals},
+    batch::{self, BatchReport},
+    config::{self, Args, Command, Commands, Config, Format},
+    page,
+    pool::{self, SessionPool},
+    transaction, Error,
+};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::Path,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -16,49 +22,156 @@ async fn main() -> Result<(), Error> {
     let config_file = File::open("config.yml").expect(
         "Missing config.yml with required fields: cluster, wait_time, tx_limit, output_file_path",
     );
-    let config: Config = serde_yaml::from_reader(config_file).expect("Couldn't read config values");
+    let mut config: Config =
+        serde_yaml::from_reader(config_file).expect("Couldn't read config values");
     log::info!("Retrieved configuration from config.yml: {:?}", &config);
 
-    let client = config::new_webdriver_client()
-        .await
-        .expect("Client not created");
-
     let args = Args::parse();
-    let cluster = match config.cluster.to_lowercase().trim() {
-        "mainnet" => Cluster::Mainnet,
-        "devnet" => Cluster::Devnet,
-        "testnet" => Cluster::Testnet,
-        _ => {
-            log::info!("Invalid cluster..Defaulting to mainnet");
-            Cluster::Mainnet
+    let mut history = HistoryRange::default();
+    let mut hide_zero_balances = false;
+    let (command, id, options) = match &args.command {
+        Commands::Account {
+            address,
+            tx_limit,
+            until,
+            since,
+            hide_zero_balances: hide_zero,
+            options,
+        } => {
+            if let Some(tx_limit) = tx_limit {
+                config.tx_limit = *tx_limit;
+            }
+            history.until = until.clone();
+            history.since = *since;
+            hide_zero_balances = *hide_zero;
+            (Command::Account, address, options)
         }
+        Commands::Tx { signature, options } => (Command::Transaction, signature, options),
     };
-    log::info!("Cluster detected: {:?}", cluster);
+    config.override_with(options);
+    history.limit = config.tx_limit as usize;
+    log::info!("Scraping {} on {}", config.cluster, config.explorer_url);
+    let mints = MintDecimals::default();
 
-    let result = match args.parse.to_lowercase().trim() {
-        "account" => {
-            let url = config::construct_url(&cluster, &Command::Account, &args.id);
-            let result = account::get_account_info(
-                &url,
-                config.tx_limit as usize,
-                config.wait_time,
-                &client,
-            )
-            .await
-            .expect("Failed getting account info");
-            log::info!("Retrieved results for account {}. Converting...", &args.id);
-            serde_json::to_string_pretty(&result).expect("Failed converting result to json")
+    let result = if let Some(path) = &options.html_file {
+        log::info!("Parsing saved page {}", path.display());
+        let html = std::fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Failed reading {}", path.display()));
+        match command {
+            Command::Account => {
+                let result = account::parse_account_html(&html, &history)
+                    .expect("Failed parsing account page");
+                render(&result, config.output_format)
+            }
+            Command::Transaction => {
+                let result = transaction::parse_transaction_html(&html, &config.explorer_url)
+                    .expect("Failed parsing transaction page");
+                render(&result, config.output_format)
+            }
         }
-        "transaction" => {
-            let url = config::construct_url(&cluster, &Command::Transaction, &args.id);
-            let result = transaction::get_transaction_info(&url, config.wait_time, &client)
+    } else if let Some(path) = &options.input_file {
+        let ids = read_ids(path).unwrap_or_else(|_| panic!("Failed reading {}", path.display()));
+        log::info!(
+            "Read {} ids from {}. Scraping with {} sessions",
+            ids.len(),
+            path.display(),
+            config.concurrency
+        );
+        let pool = SessionPool::new(config.concurrency, || {
+            config::new_webdriver_client(&config.webdriver)
+        });
+        let (explorer, cluster) = (&config.explorer_url, &config.cluster);
+        let wait = config.page_wait();
+
+        match command {
+            Command::Account => {
+                let job = pool::job(|id, client| {
+                    let url = config::construct_url(explorer, cluster, &Command::Account, &id);
+                    let history = history.clone();
+                    let mints = mints.clone();
+                    Box::pin(async move {
+                        account::get_account_info(
+                            &url,
+                            &history,
+                            hide_zero_balances,
+                            &wait,
+                            &mints,
+                            client,
+                        )
+                        .await
+                    })
+                });
+                let mut report = BatchReport::new();
+                for (id, result) in pool.run(ids, &job).await {
+                    report.push(id, result);
+                }
+                log::info!(
+                    "Scraped {} accounts, {} failed. Converting...",
+                    report.succeeded,
+                    report.failed
+                );
+                render(&report, config.output_format)
+            }
+            Command::Transaction => {
+                let job = pool::job(|id, client| {
+                    let url = config::construct_url(explorer, cluster, &Command::Transaction, &id);
+                    let mints = mints.clone();
+                    Box::pin(async move {
+                        transaction::get_transaction_info(&url, &wait, &mints, client).await
+                    })
+                });
+                let mut report = BatchReport::new();
+                for (id, result) in pool.run(ids, &job).await {
+                    report.push(id, result);
+                }
+                log::info!(
+                    "Scraped {} transactions, {} failed. Converting...",
+                    report.succeeded,
+                    report.failed
+                );
+                render(&report, config.output_format)
+            }
+        }
+    } else {
+        let id = id.as_deref().expect("Missing id");
+        let client = config::new_webdriver_client(&config.webdriver)
+            .await
+            .expect("Client not created");
+        let url = config::construct_url(&config.explorer_url, &config.cluster, &command, id);
+        if let Some(path) = &options.save_html {
+            let mut webdriver = client.lock().await;
+            webdriver.goto(&url).await?;
+            let html = page::wait_for_page(&mut webdriver, &config.page_wait())
                 .await
-                .expect("Failed getting transaction info");
-            log::info!("Retrieved results for account {}. Converting...", &args.id);
-            serde_json::to_string_pretty(&result).expect("Failed converting result to json")
+                .expect("Failed loading page");
+            std::fs::write(path, html)
+                .unwrap_or_else(|_| panic!("Failed writing {}", path.display()));
+            log::info!("Saved {} to {}", url, path.display());
+            return Ok(());
         }
-        _ => {
-            panic!("Program shutdown, no command detected");
+        match command {
+            Command::Account => {
+                let result = account::get_account_info(
+                    &url,
+                    &history,
+                    hide_zero_balances,
+                    &config.page_wait(),
+                    &mints,
+                    &client,
+                )
+                .await
+                .expect("Failed getting account info");
+                log::info!("Retrieved results for account {}. Converting...", id);
+                render(&result, config.output_format)
+            }
+            Command::Transaction => {
+                let result =
+                    transaction::get_transaction_info(&url, &config.page_wait(), &mints, &client)
+                        .await
+                        .expect("Failed getting transaction info");
+                log::info!("Retrieved results for account {}. Converting...", id);
+                render(&result, config.output_format)
+            }
         }
     };
 
@@ -71,3 +184,20 @@ async fn main() -> Result<(), Error> {
 
     Ok(())
 }
+
+fn read_ids(path: &Path) -> io::Result<Vec<String>> {
+    if path == Path::new("-") {
+        batch::read_ids(io::stdin().lock())
+    } else {
+        batch::read_ids(BufReader::new(File::open(path)?))
+    }
+}
+
+fn render<T: Serialize>(result: &T, format: Format) -> String {
+    match format {
+        Format::Json => {
+            serde_json::to_string_pretty(result).expect("Failed converting result to json")
+        }
+        Format::Yaml => serde_yaml::to_string(result).expect("Failed converting result to yaml"),
+    }
+}
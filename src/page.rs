@@ -0,0 +1,142 @@
+use crate::error::ScrapeError;
+use fantoccini::Client;
+use select::{
+    document::Document,
+    predicate::{Class, Predicate},
+};
+use std::time::{Duration, Instant};
+
+/// How long to wait for an explorer page to render, and how often to check on it.
+#[derive(Debug, Clone, Copy)]
+pub struct Wait {
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PageState {
+    Loading,
+    Ready,
+    NotFound(String),
+    Failed(String),
+}
+
+impl PageState {
+    /// Classifies a rendered page. A page is ready once it shows a titled card and
+    /// nothing is still loading. A page with only a bare message card is the
+    /// explorer reporting an error.
+    pub fn of(document: &Document) -> PageState {
+        let loading = document
+            .find(Class("spinner-grow").or(Class("spinner-border")))
+            .next()
+            .is_some();
+        if loading {
+            return PageState::Loading;
+        }
+
+        if document.find(Class("card-header-title")).next().is_some() {
+            return PageState::Ready;
+        }
+
+        let message = document
+            .find(Class("card").child(Class("card-body").and(Class("text-center"))))
+            .next()
+            .map(|x| {
+                x.children()
+                    .filter(|x| !x.is(Class("btn")))
+                    .map(|x| x.text())
+                    .collect::<String>()
+            });
+        match message {
+            Some(message) => {
+                let message = message.trim().to_string();
+                let lowercase = message.to_lowercase();
+                if lowercase.contains("not found")
+                    || lowercase.contains("not valid")
+                    || lowercase.contains("does not exist")
+                {
+                    PageState::NotFound(message)
+                } else {
+                    PageState::Failed(message)
+                }
+            }
+            None => PageState::Loading,
+        }
+    }
+}
+
+/// Waits until the current page has rendered and returns its source.
+///
+/// Returns an error if the explorer reports a missing or failed page, or if the
+/// page hasn't settled by `wait.timeout`. A page without any card yet counts as
+/// loading, so this is a single wait bounded by `wait.timeout`.
+pub async fn wait_for_page(webdriver: &mut Client, wait: &Wait) -> Result<String, ScrapeError> {
+    wait_for_change(webdriver, wait, |_| true).await
+}
+
+/// Waits until the current page has settled and `changed` holds for it, e.g.
+/// after clicking a control that loads more content. Returns the page source.
+pub async fn wait_for_change(
+    webdriver: &mut Client,
+    wait: &Wait,
+    changed: impl Fn(&Document) -> bool,
+) -> Result<String, ScrapeError> {
+    let deadline = Instant::now() + wait.timeout;
+
+    loop {
+        let html = webdriver.source().await?;
+        let document = Document::from(html.as_str());
+        match PageState::of(&document) {
+            PageState::Ready if changed(&document) => return Ok(html),
+            PageState::NotFound(message) => return Err(ScrapeError::NotFound { message }),
+            PageState::Failed(message) => return Err(ScrapeError::Explorer { message }),
+            PageState::Ready | PageState::Loading => {}
+        }
+
+        if Instant::now() >= deadline {
+            return Err(ScrapeError::Timeout {
+                seconds: wait.timeout.as_secs(),
+            });
+        }
+        tokio::time::sleep(wait.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(html: &str) -> PageState {
+        PageState::of(&Document::from(html))
+    }
+
+    #[test]
+    fn saved_pages_are_ready() {
+        let html = include_str!("../tests/fixtures/transaction/v0_no_token_balances.html");
+        assert_eq!(state(html), PageState::Ready);
+
+        let html = include_str!("../tests/fixtures/account/no_history.html");
+        assert_eq!(state(html), PageState::Ready);
+    }
+
+    #[test]
+    fn spinners_mean_loading() {
+        let html = r#"<div class="card"><div class="card-header"><h3 class="card-header-title">Overview</h3></div></div>
+<div class="card"><div class="card-body text-center"><span class="align-text-top spinner-grow spinner-grow-sm me-2"></span>Loading</div></div>"#;
+        assert_eq!(state(html), PageState::Loading);
+    }
+
+    #[test]
+    fn pages_without_cards_are_loading() {
+        assert_eq!(state(r#"<div id="root"></div>"#), PageState::Loading);
+    }
+
+    #[test]
+    fn detects_explorer_errors() {
+        let html = r#"<div class="card"><div class="card-body text-center">Not Found<span class="btn btn-white ms-3 d-none d-md-inline">Try Again</span></div></div>"#;
+        assert_eq!(state(html), PageState::NotFound("Not Found".to_string()));
+
+        let html = r#"<div class="card"><div class="card-body text-center">Fetch Failed<span class="btn btn-white ms-3 d-none d-md-inline">Try Again</span></div></div>"#;
+        assert_eq!(state(html), PageState::Failed("Fetch Failed".to_string()));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{error::ScrapeError, Error};
+use fantoccini::Client;
+use futures_util::future::{join_all, LocalBoxFuture};
+use std::{collections::VecDeque, future::Future};
+use tokio::sync::Mutex;
+
+/// Scrapes one id with whichever webdriver session is free.
+pub type Job<'j, T> =
+    dyn for<'s> Fn(String, &'s Mutex<Client>) -> LocalBoxFuture<'s, Result<T, ScrapeError>> + 'j;
+
+type Scraped<T> = (String, Result<T, ScrapeError>);
+
+/// Pins down the signature of a closure so it can be used as a [`Job`].
+pub fn job<T, F>(job: F) -> F
+where
+    F: for<'s> Fn(String, &'s Mutex<Client>) -> LocalBoxFuture<'s, Result<T, ScrapeError>>,
+{
+    job
+}
+
+/// A pool of independent webdriver sessions that share a queue of ids.
+pub struct SessionPool<C> {
+    sessions: usize,
+    connect: C,
+}
+
+impl<C, F> SessionPool<C>
+where
+    C: Fn() -> F,
+    F: Future<Output = Result<Mutex<Client>, Error>>,
+{
+    /// `connect` opens a new session. It's called once per worker, and again
+    /// whenever a worker's session is lost.
+    pub fn new(sessions: usize, connect: C) -> Self {
+        SessionPool {
+            sessions: sessions.max(1),
+            connect,
+        }
+    }
+
+    /// Runs `job` for every id and returns the results in input order.
+    ///
+    /// An id whose session crashes is retried once on a replacement session.
+    pub async fn run<'a, T>(&'a self, ids: Vec<String>, job: &'a Job<'a, T>) -> Vec<Scraped<T>> {
+        let total = ids.len();
+        let queue = Mutex::new(ids.into_iter().enumerate().collect::<VecDeque<_>>());
+        let results = Mutex::new((0..total).map(|_| None).collect::<Vec<_>>());
+
+        let workers =
+            (0..self.sessions.min(total)).map(|worker| self.worker(worker, &queue, &results, job));
+        join_all(workers).await;
+
+        // Ids left in the queue had no session to run on.
+        for (index, id) in queue.into_inner() {
+            results.lock().await[index] = Some((id, Err(ScrapeError::NoSession)));
+        }
+
+        results.into_inner().into_iter().flatten().collect()
+    }
+
+    async fn worker<'a, T>(
+        &'a self,
+        worker: usize,
+        queue: &'a Mutex<VecDeque<(usize, String)>>,
+        results: &'a Mutex<Vec<Option<Scraped<T>>>>,
+        job: &'a Job<'a, T>,
+    ) {
+        let mut session = match (self.connect)().await {
+            Ok(session) => session,
+            Err(err) => {
+                log::error!(
+                    "Worker {} couldn't open a webdriver session: {}",
+                    worker,
+                    err
+                );
+                return;
+            }
+        };
+
+        loop {
+            let next = queue.lock().await.pop_front();
+            let (index, id) = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            let mut result = job(id.clone(), &session).await;
+            if let Some(err) = result.as_ref().err().filter(|err| err.is_session_lost()) {
+                log::warn!("Worker {} lost its session on {}: {}", worker, id, err);
+                let _ = session.lock().await.close().await;
+                match (self.connect)().await {
+                    Ok(replacement) => {
+                        session = replacement;
+                        result = job(id.clone(), &session).await;
+                    }
+                    Err(err) => {
+                        log::error!("Worker {} couldn't replace its session: {}", worker, err);
+                        results.lock().await[index] = Some((id, result));
+                        return;
+                    }
+                }
+            }
+            results.lock().await[index] = Some((id, result));
+        }
+
+        let closed = session.lock().await.close().await;
+        if let Err(err) = closed {
+            log::warn!("Worker {} couldn't close its session: {}", worker, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_ids_left_without_a_session() {
+        let pool = SessionPool::new(2, || async {
+            Err::<Mutex<Client>, Error>("connection refused".into())
+        });
+        let job = job(|_, _| Box::pin(async { Ok(()) }));
+
+        let results = pool.run(vec!["a".to_string(), "b".to_string()], &job).await;
+
+        let ids = results.iter().map(|x| x.0.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(results
+            .iter()
+            .all(|x| matches!(x.1, Err(ScrapeError::NoSession))));
+    }
+}
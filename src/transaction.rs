@@ -1,117 +1,389 @@
-use crate::Error;
-use fantoccini::Client;
+use crate::{
+    account::MintDecimals,
+    address::{is_pubkey, AddressLink},
+    amount::{Amount, Lamports, LamportsChange, TokenAmount, TokenChange},
+    error::{parse_number, OrMissing, ScrapeError},
+    page::{wait_for_change, wait_for_page, Wait},
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use fantoccini::{error::CmdError, Client};
 use select::{
     document::Document,
     node::Node,
     predicate::{Class, Name, Predicate},
 };
 use serde::Serialize;
-use std::{
-    collections::{HashMap, HashSet},
-    thread,
-    time::Duration,
-};
 use tokio::sync::Mutex;
+use url::Url;
 
 #[derive(Debug, Default, Serialize)]
 pub struct Transaction {
     pub overview: TxOverview,
     pub token_balances: Option<Vec<TokenAccountInfo>>,
     pub account_inputs: Vec<TxAccountInput>,
+    pub address_table_lookups: Option<Vec<AddressTableLookup>>,
     pub instructions: Vec<Instruction>,
+    pub program_logs: Vec<ProgramInvocation>,
 }
 
 #[derive(Default, Debug, Serialize)]
 pub struct Instruction {
     description: String,
     program: String,
+    program_label: Option<String>,
     accounts: Vec<(String, IxAccountContext)>,
-    additional_info: HashMap<String, String>,
-    hex: Option<String>,
+    /// The decoded fields that are not accounts, in page order.
+    additional_info: Vec<InstructionField>,
+    raw: Option<RawInstruction>,
+    inner_instructions: Vec<Instruction>,
+}
+
+/// A decoded instruction field, e.g. `Transfer Amount (SOL)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstructionField {
+    pub name: String,
+    #[serde(flatten)]
+    pub value: FieldValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum FieldValue {
+    Pubkey(String),
+    Integer(i64),
+    /// A decimal number, or an integer too large for `Integer`.
+    Decimal(Amount<i128>),
+    /// Anything else as displayed, e.g. `10,000 micro-lamports`.
+    Text(String),
+}
+
+impl FieldValue {
+    fn classify(text: &str) -> Self {
+        let text = text.trim();
+        // Checked first: pubkeys such as the System Program's are all digits.
+        if is_pubkey(text) {
+            return FieldValue::Pubkey(text.to_string());
+        }
+        match Amount::<i128>::parse(text, "") {
+            Ok(amount) if !text.contains('.') => i64::try_from(amount.raw)
+                .map(FieldValue::Integer)
+                .unwrap_or(FieldValue::Decimal(amount)),
+            Ok(amount) => FieldValue::Decimal(amount),
+            Err(_) => FieldValue::Text(text.to_string()),
+        }
+    }
+}
+
+/// An instruction exactly as it appears in the transaction message.
+#[derive(Default, Debug, Serialize)]
+pub struct RawInstruction {
+    accounts: Vec<IxAccountContext>,
+    data_hex: String,
+    data_base58: String,
 }
 
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct IxAccountContext {
     address: String,
-    attributes: Option<Vec<String>>,
+    label: Option<String>,
+    attributes: AccountAttributes,
+}
+
+/// The badges the explorer shows next to an account.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountAttributes {
+    pub signer: bool,
+    pub writable: bool,
+    pub fee_payer: bool,
+    pub program: bool,
+    pub lookup_table_loaded: bool,
+    /// Badges without a flag of their own, as displayed.
+    pub other: Vec<String>,
+}
+
+impl AccountAttributes {
+    fn from_badges<'a>(badges: impl Iterator<Item = Node<'a>>) -> Self {
+        let mut attributes = AccountAttributes::default();
+        for badge in badges {
+            let badge = badge.text();
+            match badge.trim() {
+                "Signer" => attributes.signer = true,
+                "Writable" => attributes.writable = true,
+                "Fee Payer" => attributes.fee_payer = true,
+                "Program" => attributes.program = true,
+                "Address Table Lookup" | "Lookup Table" => attributes.lookup_table_loaded = true,
+                other => {
+                    if !attributes.other.iter().any(|x| x == other) {
+                        attributes.other.push(other.to_string());
+                    }
+                }
+            }
+        }
+        attributes
+    }
 }
 
 #[derive(Default, Debug, Serialize)]
 pub struct TxAccountInput {
     address: String,
-    attributes: Vec<String>,
-    sol_change: f64,
-    post_balance: f64,
+    label: Option<String>,
+    attributes: AccountAttributes,
+    sol_change: LamportsChange,
+    post_balance: Lamports,
+}
+
+/// An address lookup table a v0 transaction loads accounts from.
+#[derive(Default, Debug, Serialize)]
+pub struct AddressTableLookup {
+    address: String,
+    writable_indexes: Vec<u8>,
+    readonly_indexes: Vec<u8>,
+    /// Addresses the indexes resolve to, in table order.
+    loaded_addresses: Vec<String>,
 }
 
 #[derive(Default, Debug, Serialize)]
 pub struct TxOverview {
     signature: String,
-    result: String,
-    timestamp: String,
+    result: TxResult,
+    timestamp: DateTime<Utc>,
+    unix_timestamp: i64,
     confirmation_status: String,
     confirmations: String,
     slot: u64,
     recent_blockhash: String,
-    fee: f64,
+    fee: Lamports,
     transaction_version: String,
 }
 
+#[derive(Default, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TxResult {
+    #[default]
+    Success,
+    Failed(TxError),
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Serialize)]
+pub struct TxError {
+    /// The error as shown by the explorer.
+    message: String,
+    /// Position of the failing instruction in `instructions`, counting from 0.
+    instruction_index: Option<usize>,
+    /// Code of a `custom program error`.
+    custom_error_code: Option<u32>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct ProgramInvocation {
+    program: String,
+    depth: usize,
+    logs: Vec<String>,
+    compute_units: Option<ComputeUnits>,
+    success: Option<bool>,
+    error: Option<String>,
+    inner_invocations: Vec<ProgramInvocation>,
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Serialize)]
+pub struct ComputeUnits {
+    consumed: u64,
+    limit: u64,
+}
+
 #[derive(Default, Debug, Serialize)]
 pub struct TokenAccountInfo {
     address: String,
-    token_name: String,
+    label: Option<String>,
+    mint: String,
+    token_name: Option<String>,
     token_url: String,
-    change: f64,
-    post_balance: String,
+    /// With the mint's decimals, see [`MintDecimals`].
+    change: TokenChange,
+    post_balance: TokenAmount,
+}
+
+impl TokenAccountInfo {
+    /// Brings the amounts to the decimals of the mint.
+    fn set_mint_decimals(&mut self, decimals: u8, section: &str) -> Result<(), ScrapeError> {
+        let too_precise = || ScrapeError::layout(section, "more decimals than its mint");
+        self.change = self
+            .change
+            .with_decimals(decimals)
+            .ok_or_else(too_precise)?;
+        self.post_balance = self
+            .post_balance
+            .with_decimals(decimals)
+            .ok_or_else(too_precise)?;
+        Ok(())
+    }
 }
 
 pub async fn get_transaction_info(
     url: &str,
-    wait_time: u64,
+    wait: &Wait,
+    mints: &MintDecimals,
     client: &Mutex<Client>,
-) -> Result<Transaction, Error> {
+) -> Result<Transaction, ScrapeError> {
     log::info!("Parsing data for url: {}", url);
 
     let mut webdriver = client.lock().await;
     webdriver.goto(url).await?;
 
     log::info!("Hold on. Retrieving document...");
-    thread::sleep(Duration::from_secs(wait_time));
-    let html = webdriver.source().await?;
+    let html = wait_for_page(&mut webdriver, wait).await?;
+    let page = webdriver.current_url().await?;
+    let mut transaction = parse_transaction_html(&html, &page)?;
+
+    // The raw view replaces the decoded fields of a card, so it's read separately.
+    let clicked = webdriver.execute(SHOW_RAW_INSTRUCTIONS, vec![]).await?;
+    if clicked.as_u64().unwrap_or_default() > 0 {
+        log::info!("Switched instructions to their raw view, waiting for the raw data...");
+        match wait_for_change(&mut webdriver, wait, raw_view_ready).await {
+            Ok(raw_html) => attach_raw_instructions(&mut transaction, &raw_html, &page)?,
+            Err(ScrapeError::Timeout { seconds }) => log::warn!(
+                "Raw instructions of {} didn't load within {}s, keeping decoded fields only",
+                url,
+                seconds
+            ),
+            Err(err) => return Err(err),
+        }
+    }
+
+    for (index, info) in transaction.token_balances.iter_mut().flatten().enumerate() {
+        let url = Url::parse(&info.token_url).map_err(CmdError::from)?;
+        let decimals = mints.get(&mut webdriver, &info.mint, &url, wait).await?;
+        info.set_mint_decimals(decimals, &format!("Token Balances row {}", index + 1))?;
+    }
 
-    let document = Document::from(html.as_str());
+    Ok(transaction)
+}
+
+/// Clicks the Raw toggle of every instruction card still showing its decoded
+/// view, and returns how many were clicked.
+const SHOW_RAW_INSTRUCTIONS: &str = r#"
+let clicked = 0;
+for (const header of document.querySelectorAll(".card-header")) {
+    if (!header.querySelector(".card-header-title .badge")) continue;
+    const toggle = [...header.querySelectorAll("button")].find((x) => x.textContent.trim() === "Raw");
+    if (toggle && !toggle.classList.contains("active")) {
+        toggle.click();
+        clicked++;
+    }
+}
+return clicked;
+"#;
+
+/// Whether every instruction card shows its raw view with the instruction data.
+fn raw_view_ready(document: &Document) -> bool {
+    document
+        .find(Class("card"))
+        .filter(|card| {
+            card.find(Class("card-header-title").descendant(Class("badge")))
+                .next()
+                .is_some()
+        })
+        .all(|card| {
+            let active = card
+                .find(Class("card-header").descendant(Class("btn").and(Class("active"))))
+                .next()
+                .is_some();
+            active && card_rows(&card).any(|row| is_hex_data_row(&row))
+        })
+}
+
+/// Fills in the raw instructions from a page where every instruction card was
+/// switched to its raw view.
+pub fn attach_raw_instructions(
+    transaction: &mut Transaction,
+    raw_html: &str,
+    page: &Url,
+) -> Result<(), ScrapeError> {
+    let raw = parse_transaction_html(raw_html, page)?;
+    merge_raw_instructions(&mut transaction.instructions, raw.instructions)
+}
+
+fn merge_raw_instructions(
+    instructions: &mut [Instruction],
+    raw: Vec<Instruction>,
+) -> Result<(), ScrapeError> {
+    if instructions.len() != raw.len() {
+        return Err(ScrapeError::layout(
+            "Raw instructions",
+            format!(
+                "expected {} instructions, found {}",
+                instructions.len(),
+                raw.len()
+            ),
+        ));
+    }
+    for (instruction, raw) in instructions.iter_mut().zip(raw) {
+        if raw.raw.is_some() {
+            instruction.raw = raw.raw;
+        }
+        merge_raw_instructions(&mut instruction.inner_instructions, raw.inner_instructions)?;
+    }
+    Ok(())
+}
+
+/// Parses a rendered explorer transaction page. Links on it are resolved
+/// against `page`, the url it was loaded from, or the explorer's url for a
+/// saved page. Token amounts keep the decimals the page shows.
+pub fn parse_transaction_html(html: &str, page: &Url) -> Result<Transaction, ScrapeError> {
+    let document = Document::from(html);
 
     let mut overview = TxOverview::default();
     let mut account_inputs = vec![];
     let mut token_balances: Option<Vec<TokenAccountInfo>> = None;
+    let mut address_table_lookups: Option<Vec<AddressTableLookup>> = None;
     let mut instructions = vec![];
+    let mut program_logs = vec![];
 
-    let cards = document
-        .find(Class("card"))
-        .filter(|x| x.parent().unwrap().attr("class").unwrap() != "inner-cards");
+    // Inner instruction cards are parsed along with the card they're nested in.
+    let cards = document.find(Class("card")).filter(|x| {
+        x.parent()
+            .is_none_or(|parent| !parent.is(Class("inner-cards")))
+    });
 
     for card in cards {
-        let title = card.find(Class("card-header-title")).next().unwrap().text();
+        let title = card
+            .find(Class("card-header-title"))
+            .next()
+            .or_missing("card", ".card-header-title")?
+            .text();
         match title.trim() {
             "Overview" => {
                 log::info!("Parsing tx overview details...");
-                overview = parse_overview(&card);
+                overview = parse_overview(&card)?;
             }
             "Account Input(s)" => {
                 log::info!("Parsing account inputs");
-                account_inputs = parse_account_inputs(&card);
+                account_inputs = parse_account_inputs(&card)?;
+            }
+            "Address Table Lookup(s)" => {
+                log::info!("Parsing address table lookups...");
+                address_table_lookups = Some(parse_address_table_lookups(&card)?);
             }
             "Token Balances" => {
                 log::info!("Parsing token balances...");
-                token_balances = Some(parse_token_balances(&card));
+                token_balances = Some(parse_token_balances(&card, page)?);
             }
             "Program Instruction Logs" => {
-                log::info!("Parsing Program Ix Logs is unimplemented. Skipping...");
+                log::info!("Parsing program instruction logs...");
+                program_logs = parse_program_logs(&card)?;
             }
             _ => {
                 log::info!("Parsing instruction...");
-                instructions.push(parse_instruction(&card))
+                instructions.push(parse_instruction(&card)?)
+            }
+        }
+    }
+
+    // Loaded accounts carry a badge too, but the lookup card is the authority.
+    for lookup in address_table_lookups.iter().flatten() {
+        for account in account_inputs.iter_mut() {
+            if lookup.loaded_addresses.contains(&account.address) {
+                account.attributes.lookup_table_loaded = true;
             }
         }
     }
@@ -120,144 +392,289 @@ pub async fn get_transaction_info(
         overview,
         token_balances,
         account_inputs,
+        address_table_lookups,
         instructions,
+        program_logs,
     };
 
     Ok(transaction)
 }
 
-fn parse_overview(overview: &Node) -> TxOverview {
-    let mut items = overview.find(Class("list").descendant(Name("tr")));
-    let signature = items
-        .next()
-        .unwrap()
+fn parse_overview(overview: &Node) -> Result<TxOverview, ScrapeError> {
+    // Failed transactions have an extra Error row, so rows are looked up by their label.
+    let rows = overview
+        .find(Class("list").descendant(Name("tr")))
+        .filter_map(|row| {
+            let label = row.find(Name("td")).next()?.text();
+            Some((label.trim().to_string(), row))
+        })
+        .collect::<Vec<_>>();
+    let row = |label: &str| {
+        rows.iter()
+            .find(|x| x.0.starts_with(label))
+            .map(|x| x.1)
+            .or_missing("Overview", &format!("{} row", label))
+    };
+
+    let signature = row("Signature")?
         .find(Class("font-monospace"))
         .next()
-        .unwrap()
-        .text();
-    let result = items
-        .next()
-        .unwrap()
-        .find(Class("bg-success-soft"))
-        .next()
-        .unwrap()
+        .or_missing("Overview: Signature", ".font-monospace")?
         .text();
-    let timestamp = items
+    let result = row("Result")?
+        .find(Class("badge"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Result", ".badge")?;
+    let result = if result.is(Class("bg-success-soft")) {
+        TxResult::Success
+    } else {
+        let message = row("Error")?
+            .find(Class("badge"))
+            .next()
+            .or_missing("Overview: Error", ".badge")?
+            .text();
+        TxResult::Failed(parse_tx_error(message.trim()))
+    };
+    let timestamp = row("Timestamp")?
         .find(Class("font-monospace"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Timestamp", ".font-monospace")?
         .text();
-    let confirmation_status = items
-        .next()
-        .unwrap()
+    let timestamp = parse_timestamp(timestamp.trim(), "Overview: Timestamp")?;
+    let confirmation_status = row("Confirmation Status")?
         .find(Class("text-lg-end"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Confirmation Status", ".text-lg-end")?
         .text();
-    let confirmations = items
-        .next()
-        .unwrap()
+    let confirmations = row("Confirmations")?
         .find(Class("text-lg-end"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Confirmations", ".text-lg-end")?
         .text();
-    let slot = items.next().unwrap().find(Name("a")).next().unwrap().text();
-    let slot = slot.split(',').collect::<String>().parse().unwrap();
-    let recent_blockhash = items
+    let slot = row("Slot")?
+        .find(Name("a"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Slot", "a")?
+        .text();
+    let slot = parse_number(&slot, "slot")?;
+    let recent_blockhash = row("Recent Blockhash")?
         .find(Class("text-lg-end"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Recent Blockhash", ".text-lg-end")?
         .text();
-    let fee: f64 = items
-        .next()
-        .unwrap()
+    let fee = row("Fee")?
         .find(Class("font-monospace"))
         .next()
-        .unwrap()
-        .text()
-        .parse()
-        .unwrap();
-    let transaction_version = items
-        .next()
-        .unwrap()
+        .or_missing("Overview: Fee", ".font-monospace")?
+        .text();
+    let fee = Lamports::parse(&fee, "fee")?;
+    let transaction_version = row("Transaction Version")?
         .find(Class("text-lg-end"))
         .next()
-        .unwrap()
+        .or_missing("Overview: Transaction Version", ".text-lg-end")?
         .text();
 
-    TxOverview {
+    Ok(TxOverview {
         signature,
         result,
         timestamp,
+        unix_timestamp: timestamp.timestamp(),
         confirmation_status,
         confirmations,
         slot,
         recent_blockhash,
         fee,
         transaction_version,
+    })
+}
+
+/// Parses a timestamp as the explorer displays it in a browser set to UTC, e.g.
+/// `Jan 27, 2023 at 17:31:08 Coordinated Universal Time`. `section` names the
+/// field in errors.
+pub(crate) fn parse_timestamp(
+    timestamp: &str,
+    section: &str,
+) -> Result<DateTime<Utc>, ScrapeError> {
+    let unexpected = || ScrapeError::layout(section, format!("unexpected format {:?}", timestamp));
+
+    let (date, time) = timestamp.split_once(" at ").ok_or_else(unexpected)?;
+    let (time, zone) = time.split_once(' ').ok_or_else(unexpected)?;
+    if !matches!(zone, "Coordinated Universal Time" | "UTC" | "GMT") {
+        return Err(ScrapeError::layout(
+            section,
+            format!("expected a UTC timestamp, the browser is set to {:?}", zone),
+        ));
+    }
+
+    let date = NaiveDate::parse_from_str(date, "%b %d, %Y").map_err(|_| unexpected())?;
+    // Some browsers show midnight as 24:00:00 in 24-hour time.
+    let time = match time.strip_prefix("24:") {
+        Some(rest) => format!("00:{}", rest),
+        None => time.to_string(),
+    };
+    let time = NaiveTime::parse_from_str(&time, "%H:%M:%S").map_err(|_| unexpected())?;
+
+    Ok(DateTime::<Utc>::from_utc(date.and_time(time), Utc))
+}
+
+/// Picks the failing instruction and custom error code out of an error such as
+/// `Error processing Instruction 1: custom program error: 0x1`.
+fn parse_tx_error(message: &str) -> TxError {
+    let instruction = message
+        .strip_prefix("Error processing Instruction ")
+        .and_then(|x| x.split_once(':'));
+    let instruction_index = instruction.and_then(|x| x.0.trim().parse().ok());
+    let custom_error_code = instruction
+        .and_then(|x| x.1.trim().strip_prefix("custom program error: "))
+        .and_then(|code| match code.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => code.parse().ok(),
+        });
+
+    TxError {
+        message: message.to_string(),
+        instruction_index,
+        custom_error_code,
     }
 }
 
-fn parse_token_balances(token_balances: &Node) -> Vec<TokenAccountInfo> {
+fn parse_token_balances(
+    token_balances: &Node,
+    page: &Url,
+) -> Result<Vec<TokenAccountInfo>, ScrapeError> {
     let token_balances = token_balances.find(Class("list").descendant(Name("tr")));
     let mut token_accounts_info = vec![];
 
-    for info in token_balances {
-        let mut child_nodes = info.children();
+    for (index, info) in token_balances.enumerate() {
+        let section = format!("Token Balances row {}", index + 1);
+        let mut child_nodes = info.children().filter(|x| x.is(Name("td")));
         let address = child_nodes
             .next()
-            .unwrap()
+            .or_missing(&section, "td")?
             .find(Name("a"))
             .next()
-            .unwrap()
-            .text();
-        let (token_name, token_url) = {
-            let node = child_nodes.next().unwrap().find(Name("a")).next().unwrap();
-            (node.text(), node.attr("href").unwrap().to_string())
+            .or_missing(&section, "td a")?;
+        let address = AddressLink::parse(&address, &section)?;
+        let (mint, token_url) = {
+            let node = child_nodes
+                .next()
+                .or_missing(&section, "td")?
+                .find(Name("a"))
+                .next()
+                .or_missing(&section, "td a")?;
+            let href = node.attr("href").or_missing(&section, "a[href]")?;
+            let url = page.join(href).map_err(|_| {
+                ScrapeError::layout(&section, format!("invalid token link {:?}", href))
+            })?;
+            (AddressLink::parse(&node, &section)?, url.to_string())
         };
-        let change: f64 = child_nodes
+        let change = child_nodes
             .next()
-            .unwrap()
+            .or_missing(&section, "td")?
             .first_child()
-            .unwrap()
-            .text()
-            .parse()
-            .unwrap();
-        let post_balance = child_nodes.next().unwrap().text();
+            .or_missing(&section, "td > *")?
+            .text();
+        let change = TokenChange::parse(&change, "change")?;
+        // The amount is followed by the token symbol, or "tokens".
+        let post_balance = child_nodes.next().or_missing(&section, "td")?.text();
+        let post_balance = TokenAmount::parse(
+            post_balance.trim().split(' ').next().unwrap_or_default(),
+            "post_balance",
+        )?;
 
         let new_token_info = TokenAccountInfo {
-            address,
-            token_name,
-            token_url: normalize_url(&token_url),
+            address: address.pubkey,
+            label: address.label,
+            mint: mint.pubkey,
+            token_name: mint.label,
+            token_url,
             change,
             post_balance,
         };
         token_accounts_info.push(new_token_info);
     }
-    token_accounts_info
+
+    Ok(token_accounts_info)
 }
 
-fn parse_account_inputs(account_inputs: &Node) -> Vec<TxAccountInput> {
+fn parse_address_table_lookups(lookups: &Node) -> Result<Vec<AddressTableLookup>, ScrapeError> {
+    let mut tables: Vec<AddressTableLookup> = vec![];
+
+    for (index, row) in lookups
+        .find(Class("list").descendant(Name("tr")))
+        .enumerate()
+    {
+        let section = format!("Address Table Lookup(s) row {}", index + 1);
+        let mut child_nodes = row.children().filter(|x| x.is(Name("td")));
+        let address = child_nodes
+            .next()
+            .or_missing(&section, "td")?
+            .find(Name("a"))
+            .next()
+            .or_missing(&section, "td a")?;
+        let address = AddressLink::parse(&address, &section)?.pubkey;
+        let table_index = child_nodes.next().or_missing(&section, "td")?.text();
+        let table_index: u8 = parse_number(&table_index, "table_index")?;
+        // The resolved address is missing while the table is still loading.
+        let resolved = child_nodes
+            .next()
+            .or_missing(&section, "td")?
+            .find(Name("a"))
+            .next()
+            .map(|x| AddressLink::parse(&x, &section))
+            .transpose()?;
+        let writable = child_nodes
+            .next()
+            .or_missing(&section, "td")?
+            .find(Class("badge"))
+            .any(|x| x.text().trim() == "Writable");
+
+        let position = match tables.iter().position(|x| x.address == address) {
+            Some(position) => position,
+            None => {
+                tables.push(AddressTableLookup {
+                    address,
+                    ..Default::default()
+                });
+                tables.len() - 1
+            }
+        };
+        let table = &mut tables[position];
+        if writable {
+            table.writable_indexes.push(table_index);
+        } else {
+            table.readonly_indexes.push(table_index);
+        }
+        if let Some(resolved) = resolved {
+            table.loaded_addresses.push(resolved.pubkey);
+        }
+    }
+
+    Ok(tables)
+}
+
+fn parse_account_inputs(account_inputs: &Node) -> Result<Vec<TxAccountInput>, ScrapeError> {
     let mut accounts_vec: Vec<TxAccountInput> = vec![];
     let tx_accounts = account_inputs.find(Class("list").descendant(Name("tr")));
 
-    for account in tx_accounts {
-        let mut child_nodes = account.children();
+    for (index, account) in tx_accounts.enumerate() {
+        let section = format!("Account Input(s) row {}", index + 1);
+        let mut child_nodes = account.children().filter(|x| x.is(Name("td")));
         _ = child_nodes.next();
         let address = child_nodes
             .next()
-            .unwrap()
+            .or_missing(&section, "td")?
             .find(Name("a"))
             .next()
-            .unwrap()
-            .text();
+            .or_missing(&section, "td a")?;
+        let address = AddressLink::parse(&address, &section)?;
 
-        let change_info = child_nodes.next().unwrap();
-        let change_sign = change_info.find(Class("badge")).next().unwrap().text();
+        let change_info = child_nodes.next().or_missing(&section, "td")?;
+        let change_sign = change_info
+            .find(Class("badge"))
+            .next()
+            .or_missing(&section, ".badge")?
+            .text();
         let mut change_amount = change_info.find(Class("font-monospace"));
         let mut amount = String::from("0");
         if let Some(value) = change_amount.next() {
@@ -266,42 +683,32 @@ fn parse_account_inputs(account_inputs: &Node) -> Vec<TxAccountInput> {
 
         let post_balance_text = child_nodes
             .next()
-            .unwrap()
+            .or_missing(&section, "td")?
             .find(Class("font-monospace"))
             .next()
-            .unwrap()
+            .or_missing(&section, ".font-monospace")?
             .text();
-        let post_balance = post_balance_text
-            .split_whitespace()
+        let post_balance = Lamports::parse(&post_balance_text, "post_balance")?;
+        let attribute_nodes = child_nodes
             .next()
-            .unwrap()
-            .trim()
-            .split(',')
-            .collect::<String>()
-            .parse::<f64>()
-            .unwrap();
-        let attribute_nodes = child_nodes.next().unwrap().find(Class("me-1"));
-
-        let mut attributes = HashSet::new();
-        for quality in attribute_nodes {
-            attributes.insert(quality.text());
-        }
-        if address.split_whitespace().any(|x| x == "Program") {
-            attributes.insert("Program".to_string());
-        }
-        let attributes = attributes.into_iter().collect::<Vec<String>>();
+            .or_missing(&section, "td")?
+            .find(Class("me-1"));
+
+        let attributes = AccountAttributes::from_badges(attribute_nodes);
 
         let sol_change = {
-            let multiplier: f64 = match change_sign.trim().chars().next().unwrap() {
-                '+' | '0' => 1.0,
-                _ => -1.0,
+            let multiplier = match change_sign.trim().chars().next() {
+                Some('+') | Some('0') => 1,
+                Some(_) => -1,
+                None => return Err(ScrapeError::layout(section, "empty balance change badge")),
             };
 
-            amount.parse::<f64>().unwrap() * multiplier
+            LamportsChange(LamportsChange::parse(&amount, "sol_change")?.0 * multiplier)
         };
 
         let new_account = TxAccountInput {
-            address,
+            address: address.pubkey,
+            label: address.label,
             attributes,
             sol_change,
             post_balance,
@@ -310,60 +717,48 @@ fn parse_account_inputs(account_inputs: &Node) -> Vec<TxAccountInput> {
         accounts_vec.push(new_account);
     }
 
-    accounts_vec
+    Ok(accounts_vec)
 }
 
-fn parse_instruction(instructions: &Node) -> Instruction {
+fn parse_instruction(instructions: &Node) -> Result<Instruction, ScrapeError> {
     let description = instructions
         .find(Class("card-header-title"))
         .next()
-        .unwrap()
+        .or_missing("Instruction", ".card-header-title")?
         .text();
-    let mut account_nodes = instructions.find(Class("list").descendant(Name("tr")));
+    let section = description.trim();
+    let mut account_nodes = card_rows(instructions);
     let program = account_nodes
         .next()
-        .unwrap()
+        .or_missing(section, "tr")?
         .find(Name("a"))
         .next()
-        .unwrap()
-        .text();
+        .or_missing(section, "Program a")?;
+    let program = AddressLink::parse(&program, section)?;
 
     let mut accounts = Vec::new();
-    let mut additional_info = HashMap::new();
-    let mut hex = None;
+    let mut additional_info = Vec::new();
+    let mut data = None;
+    let mut inner_instructions = vec![];
 
     for row in account_nodes {
-        if row
-            .first_child()
-            .unwrap()
-            .text()
-            .split("<span")
-            .next()
-            .unwrap()
-            .split_whitespace()
-            .take(2)
-            .collect::<Vec<&str>>()
-            .join(" ")
-            == "Instruction Data"
-        {
-            let data = row
-                .find(
-                    Class("text-lg-end")
-                        .descendant(Class("mb-0"))
-                        .descendant(Name("span")),
-                )
-                .map(|x| x.text().replace('\u{2003}', ""))
-                .collect::<String>()
-                .split_whitespace()
-                .collect::<String>();
-            let mid = data.len() / 2;
-            hex = Some(data[0..mid].to_string());
+        if let Some(inner_cards) = row.find(Class("inner-cards")).next() {
+            for card in inner_cards.children().filter(|x| x.is(Class("card"))) {
+                inner_instructions.push(parse_instruction(&card)?);
+            }
+            continue;
+        }
 
-            break;
+        let first_child = row.first_child().or_missing(section, "td")?;
+        if is_hex_data_row(&row) {
+            data = Some(parse_hex_data(&row, section)?);
+            continue;
+        }
+        if first_child.text().trim().starts_with("Instruction Data") {
+            // The decoded data of a parsed instruction in its raw view.
+            continue;
         }
 
-        let mut child_nodes = row.children();
-        let first_child = child_nodes.next().unwrap();
         let maybe_title = first_child.find(Class("me-2")).next();
         let title = if let Some(title) = maybe_title {
             title.text()
@@ -371,65 +766,425 @@ fn parse_instruction(instructions: &Node) -> Instruction {
             first_child.text()
         };
 
-        if let Some(address) = row.find(Name("a")).next() {
-            let attributes = row
-                .find(Class("badge"))
-                .map(|x| x.text())
-                .collect::<Vec<String>>();
-            let attributes = if attributes.is_empty() {
-                None
-            } else {
-                Some(attributes)
-            };
+        if let Some(address) = row.find(Name("a")).find(AddressLink::is_address_link) {
+            let address = AddressLink::parse(&address, section)?;
+            let attributes = AccountAttributes::from_badges(row.find(Class("badge")));
 
             let context = IxAccountContext {
-                address: address.text(),
+                address: address.pubkey,
+                label: address.label,
                 attributes,
             };
             accounts.push((title, context));
         } else {
             // we don't have an account, we get the extra information
-            let value = row.find(Class("font-monospace")).next().unwrap().text();
-            additional_info.insert(title, value);
+            let value = row
+                .find(Class("font-monospace"))
+                .next()
+                .or_missing(&format!("{}: {}", section, title.trim()), ".font-monospace")?
+                .text();
+            additional_info.push(InstructionField {
+                name: title.trim().to_string(),
+                value: FieldValue::classify(&value),
+            });
         }
     }
 
     // Only attempt sort if we have accounts labelled as Account#1, Account#2, etc
-    if accounts[0].0.contains("Account #") {
-        accounts.sort_by(sort_accounts);
+    if accounts.first().is_some_and(|x| x.0.contains("Account #")) {
+        accounts = sort_accounts(accounts, section)?;
     }
 
-    Instruction {
+    // Unparsed instructions show the raw accounts and data in their default view.
+    let raw = data.map(|data| RawInstruction {
+        accounts: accounts
+            .iter()
+            .filter(|x| x.0.contains("Account #"))
+            .map(|x| x.1.clone())
+            .collect(),
+        data_base58: bs58::encode(&data).into_string(),
+        data_hex: data.iter().map(|x| format!("{:02x}", x)).collect(),
+    });
+
+    Ok(Instruction {
         description,
-        program,
+        program: program.pubkey,
+        program_label: program.label,
         accounts,
         additional_info,
-        hex,
+        raw,
+        inner_instructions,
+    })
+}
+
+/// The rows of an instruction card, without those of the inner instruction
+/// cards nested in it.
+fn card_rows<'a>(card: &Node<'a>) -> impl Iterator<Item = Node<'a>> {
+    card.find(Class("list"))
+        .next()
+        .into_iter()
+        .flat_map(|list| list.children().filter(|x| x.is(Name("tr"))))
+}
+
+fn is_hex_data_row(row: &Node) -> bool {
+    row.first_child().is_some_and(|x| {
+        let title = x.text();
+        title.starts_with("Instruction Data") && title.contains("(Hex)")
+    })
+}
+
+/// Reads the bytes of an `Instruction Data (Hex)` row. The explorer renders the
+/// hex dump twice, for wide and narrow screens, so only the first one is read.
+fn parse_hex_data(row: &Node, section: &str) -> Result<Vec<u8>, ScrapeError> {
+    let dump = match row.find(Name("pre")).next() {
+        Some(dump) => dump,
+        // Instructions without data show "No data" instead of a dump.
+        None => return Ok(vec![]),
+    };
+    let hex = dump
+        .text()
+        .chars()
+        .filter(|x| !x.is_whitespace())
+        .collect::<String>();
+
+    let invalid = || ScrapeError::layout(section, format!("invalid instruction data {:?}", hex));
+    if hex.len() % 2 != 0 {
+        return Err(invalid());
     }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
 }
 
 fn sort_accounts(
-    a: &(String, IxAccountContext),
-    b: &(String, IxAccountContext),
-) -> std::cmp::Ordering {
-    let a_position =
-        a.0.split('#')
-            .last()
-            .unwrap()
-            .trim()
-            .parse::<u64>()
-            .unwrap();
-    let b_position =
-        b.0.split('#')
-            .last()
-            .unwrap()
+    accounts: Vec<(String, IxAccountContext)>,
+    section: &str,
+) -> Result<Vec<(String, IxAccountContext)>, ScrapeError> {
+    let mut positioned = accounts
+        .into_iter()
+        .map(|account| {
+            let position = account.0.split('#').next_back().unwrap_or_default();
+            let position: u64 = parse_number(position, &format!("{} position", section))?;
+            Ok((position, account))
+        })
+        .collect::<Result<Vec<_>, ScrapeError>>()?;
+    positioned.sort_by_key(|x| x.0);
+
+    Ok(positioned.into_iter().map(|x| x.1).collect())
+}
+
+fn parse_program_logs(program_logs: &Node) -> Result<Vec<ProgramInvocation>, ScrapeError> {
+    let mut invocations = vec![];
+
+    for (index, row) in program_logs
+        .find(Class("list").descendant(Name("tr")))
+        .enumerate()
+    {
+        let section = format!("Program Instruction Logs row {}", index + 1);
+        let program = row
+            .find(Class("program-name"))
+            .next()
+            .or_missing(&section, ".program-name")?
+            .text()
             .trim()
-            .parse::<u64>()
-            .unwrap();
+            .trim_end_matches(" Instruction")
+            .to_string();
+        let failed = row
+            .find(Class("badge"))
+            .next()
+            .or_missing(&section, ".badge")?
+            .is(Class("bg-warning-soft"));
+
+        let mut stack = vec![ProgramInvocation {
+            program,
+            depth: 1,
+            ..Default::default()
+        }];
+        let mut root = None;
+
+        // Each log line is a pair of spans: the depth prefix and the log text.
+        let lines = row
+            .find(Class("flex-column").descendant(Name("span")))
+            .filter(|x| x.parent().is_some_and(|x| x.is(Class("flex-column"))));
+        for line in lines {
+            let text = line
+                .children()
+                .filter(|x| x.is(Name("span")))
+                .skip(1)
+                .map(|x| x.text())
+                .collect::<String>();
+            let text = text.trim();
+
+            if let Some(program) = text.strip_prefix("Program invoked: ") {
+                stack.push(ProgramInvocation {
+                    program: program.to_string(),
+                    depth: stack.len() + 1,
+                    ..Default::default()
+                });
+            } else if text == "Program returned success" {
+                root = close_invocation(&mut stack, None).or(root);
+            } else if let Some(error) = text.strip_prefix("Program returned error: ") {
+                let error = error.trim_matches('"').to_string();
+                root = close_invocation(&mut stack, Some(error)).or(root);
+            } else if let Some(compute_units) = parse_compute_units(text) {
+                if let Some(current) = stack.last_mut() {
+                    current.compute_units = Some(compute_units);
+                }
+            } else if let Some(current) = stack.last_mut() {
+                let log = match text.strip_prefix("Program logged: ") {
+                    Some(log) => log.trim_matches('"'),
+                    None => text,
+                };
+                current.logs.push(log.to_string());
+            }
+        }
+
+        // Truncated logs can leave invocations open, fold them into their parents.
+        while stack.len() > 1 {
+            if let Some(child) = stack.pop() {
+                let last = stack.len() - 1;
+                stack[last].inner_invocations.push(child);
+            }
+        }
+        let mut invocation = root
+            .or_else(|| stack.pop())
+            .ok_or_else(|| ScrapeError::layout(&section, "unbalanced program logs"))?;
+        if failed && invocation.success.is_none() {
+            invocation.success = Some(false);
+        }
+
+        invocations.push(invocation);
+    }
+
+    Ok(invocations)
+}
 
-    a_position.cmp(&b_position)
+/// Pops the innermost open invocation, attaching it to its parent.
+/// Returns the invocation if it was the top-level one.
+fn close_invocation(
+    stack: &mut Vec<ProgramInvocation>,
+    error: Option<String>,
+) -> Option<ProgramInvocation> {
+    let mut invocation = stack.pop()?;
+    invocation.success = Some(error.is_none());
+    invocation.error = error;
+
+    match stack.last_mut() {
+        Some(parent) => {
+            parent.inner_invocations.push(invocation);
+            None
+        }
+        None => Some(invocation),
+    }
 }
 
-fn normalize_url(url: &str) -> String {
-    format!("https://explorer.solana.com{}", url)
+fn parse_compute_units(log: &str) -> Option<ComputeUnits> {
+    let units = log.strip_suffix(" compute units")?;
+    let (_, units) = units.split_once(" consumed ")?;
+    let (consumed, limit) = units.split_once(" of ")?;
+
+    Some(ComputeUnits {
+        consumed: consumed.trim().parse().ok()?,
+        limit: limit.trim().parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program_logs_fixture() -> Vec<ProgramInvocation> {
+        let html = include_str!("../tests/fixtures/program_logs_card.html");
+        let document = Document::from(html);
+        let card = document.find(Class("card")).next().unwrap();
+        parse_program_logs(&card).unwrap()
+    }
+
+    #[test]
+    fn parses_one_invocation_per_instruction() {
+        let invocations = program_logs_fixture();
+        let programs = invocations
+            .iter()
+            .map(|x| x.program.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(
+            programs,
+            [
+                "Compute Budget Program",
+                "Unknown Program (sunzv8N3A8dRHwUBvxgRDEbWKk8t7yiHR4FLRgFsTX6)",
+                "Token Program",
+                "Memo Program",
+            ]
+        );
+        assert!(invocations.iter().all(|x| x.depth == 1));
+    }
+
+    #[test]
+    fn builds_nested_invocation_tree() {
+        let invocations = program_logs_fixture();
+        let deposit = &invocations[1];
+
+        assert_eq!(deposit.success, Some(true));
+        assert_eq!(deposit.logs, ["Instruction: Deposit"]);
+        assert_eq!(
+            deposit.compute_units,
+            Some(ComputeUnits {
+                consumed: 27142,
+                limit: 200000
+            })
+        );
+        assert_eq!(deposit.inner_invocations.len(), 2);
+
+        let system = &deposit.inner_invocations[0];
+        assert_eq!(system.program, "System Program");
+        assert_eq!(system.depth, 2);
+        assert_eq!(system.success, Some(true));
+        assert!(system.logs.is_empty());
+        assert_eq!(system.compute_units, None);
+
+        let mint = &deposit.inner_invocations[1];
+        assert_eq!(mint.program, "Token Program");
+        assert_eq!(mint.depth, 2);
+        assert_eq!(mint.logs, ["Instruction: MintTo"]);
+        assert_eq!(
+            mint.compute_units,
+            Some(ComputeUnits {
+                consumed: 4492,
+                limit: 177350
+            })
+        );
+    }
+
+    #[test]
+    fn records_failed_invocation() {
+        let invocations = program_logs_fixture();
+        let transfer = &invocations[2];
+
+        assert_eq!(transfer.success, Some(false));
+        assert_eq!(transfer.error.as_deref(), Some("custom program error: 0x1"));
+        assert_eq!(
+            transfer.logs,
+            ["Instruction: Transfer", "Error: insufficient funds"]
+        );
+    }
+
+    #[test]
+    fn waits_for_every_card_to_show_raw_data() {
+        let decoded = include_str!("../tests/fixtures/transaction/v0_no_token_balances.html");
+        let raw = include_str!("../tests/fixtures/transaction/v0_raw_view.html");
+
+        assert!(!raw_view_ready(&Document::from(decoded)));
+        assert!(raw_view_ready(&Document::from(raw)));
+    }
+
+    #[test]
+    fn brings_token_balances_to_their_mint_decimals() {
+        let html = include_str!("../tests/fixtures/transaction/legacy_token_balances.html");
+        let page = Url::parse("https://explorer.solana.com").unwrap();
+        let transaction = parse_transaction_html(html, &page).unwrap();
+        let mut info = transaction.token_balances.unwrap().remove(2);
+        assert_eq!(info.change.decimals, 0);
+
+        info.set_mint_decimals(9, "test").unwrap();
+        assert_eq!((info.change.raw, info.change.decimals), (0, 9));
+        assert_eq!(info.post_balance.raw, 453_738_259_357);
+        assert!(info.set_mint_decimals(6, "test").is_err());
+    }
+
+    #[test]
+    fn parses_utc_timestamps() {
+        let timestamp = parse_timestamp(
+            "Jan 7, 2023 at 24:01:08 Coordinated Universal Time",
+            "timestamp",
+        )
+        .unwrap();
+        assert_eq!(timestamp.to_rfc3339(), "2023-01-07T00:01:08+00:00");
+
+        assert!(matches!(
+            parse_timestamp(
+                "Jan 27, 2023 at 18:31:08 West Africa Standard Time",
+                "timestamp"
+            ),
+            Err(ScrapeError::UnexpectedLayout { .. })
+        ));
+    }
+
+    #[test]
+    fn classifies_instruction_fields() {
+        assert_eq!(
+            FieldValue::classify("11111111111111111111111111111111"),
+            FieldValue::Pubkey("11111111111111111111111111111111".to_string())
+        );
+        assert_eq!(
+            FieldValue::classify("1,000,000"),
+            FieldValue::Integer(1_000_000)
+        );
+        assert_eq!(
+            FieldValue::classify("0.25"),
+            FieldValue::Decimal(Amount {
+                raw: 25,
+                decimals: 2
+            })
+        );
+        assert_eq!(
+            FieldValue::classify("18,446,744,073,709,551,615"),
+            FieldValue::Decimal(Amount {
+                raw: u64::MAX.into(),
+                decimals: 0
+            })
+        );
+        assert_eq!(
+            FieldValue::classify(" 10,000 micro-lamports "),
+            FieldValue::Text("10,000 micro-lamports".to_string())
+        );
+        assert_eq!(
+            FieldValue::classify("HappyBirthdayToYouMyFriendsAndCoworkers"),
+            FieldValue::Text("HappyBirthdayToYouMyFriendsAndCoworkers".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_transaction_errors() {
+        assert_eq!(
+            parse_tx_error("Error processing Instruction 3: custom program error: 0x1770"),
+            TxError {
+                message: "Error processing Instruction 3: custom program error: 0x1770".to_string(),
+                instruction_index: Some(3),
+                custom_error_code: Some(6000),
+            }
+        );
+        assert_eq!(
+            parse_tx_error("Error processing Instruction 0: insufficient funds for instruction"),
+            TxError {
+                message: "Error processing Instruction 0: insufficient funds for instruction"
+                    .to_string(),
+                instruction_index: Some(0),
+                custom_error_code: None,
+            }
+        );
+        assert_eq!(
+            parse_tx_error("Blockhash not found"),
+            TxError {
+                message: "Blockhash not found".to_string(),
+                instruction_index: None,
+                custom_error_code: None,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_instructions_without_logs() {
+        let invocations = program_logs_fixture();
+        let memo = &invocations[3];
+
+        assert_eq!(memo.success, None);
+        assert!(memo.logs.is_empty());
+        assert!(memo.inner_invocations.is_empty());
+    }
 }
@@ -0,0 +1,243 @@
+//! Golden-file tests for the page parsers.
+//!
+//! Each fixture under `tests/fixtures` is an explorer page, and the `.json`
+//! file next to it is the expected parser output. Run with `UPDATE_SNAPSHOTS=1`
+//! to regenerate the golden files after an intended change.
+//!
+//! Capture a fixture from the explorer with `dora tx <SIGNATURE> --save-html
+//! <FILE>` (or `dora account <ADDRESS> ...`). The fixtures committed so far are
+//! still synthetic: hand-written reproductions of the explorer's markup with
+//! only the structure the parsers rely on, and are to be replaced by captured
+//! pages. A new case gets a new fixture rather than changes to the input of an
+//! existing snapshot.
+
+use chrono::{TimeZone, Utc};
+use dora::{
+    account::{self, HistoryRange},
+    transaction,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{env, fs, path::PathBuf};
+use url::Url;
+
+const TX_LIMIT: usize = 10;
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn fixture(name: &str) -> String {
+    let path = fixtures_dir().join(format!("{}.html", name));
+    fs::read_to_string(&path).unwrap_or_else(|_| panic!("Missing fixture {}", path.display()))
+}
+
+fn assert_snapshot<T: Serialize>(name: &str, value: &T) {
+    let path = fixtures_dir().join(format!("{}.json", name));
+    let actual = serde_json::to_string_pretty(&serde_json::to_value(value).unwrap()).unwrap();
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, format!("{}\n", actual)).unwrap();
+        return;
+    }
+
+    let golden = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing golden file {}", path.display()));
+    let expected: Value = serde_json::from_str(&golden).unwrap();
+    let expected = serde_json::to_string_pretty(&expected).unwrap();
+    assert_eq!(actual, expected, "{} doesn't match its golden file", name);
+}
+
+fn explorer() -> Url {
+    Url::parse("https://explorer.solana.com").unwrap()
+}
+
+fn transaction_snapshot(name: &str) {
+    let result = transaction::parse_transaction_html(&fixture(name), &explorer()).unwrap();
+    assert_snapshot(name, &result);
+}
+
+fn account_snapshot(name: &str) {
+    let result = account::parse_account_html(&fixture(name), &HistoryRange::new(TX_LIMIT)).unwrap();
+    assert_snapshot(name, &result);
+}
+
+#[test]
+fn legacy_transaction_with_token_balances() {
+    transaction_snapshot("transaction/legacy_token_balances");
+}
+
+#[test]
+fn inner_instructions() {
+    transaction_snapshot("transaction/inner_instructions");
+}
+
+#[test]
+fn v0_transaction_without_token_balances() {
+    transaction_snapshot("transaction/v0_no_token_balances");
+}
+
+#[test]
+fn address_table_lookups() {
+    transaction_snapshot("transaction/address_table_lookups");
+}
+
+#[test]
+fn v0_transaction_with_raw_instructions() {
+    let explorer = explorer();
+    let mut result = transaction::parse_transaction_html(
+        &fixture("transaction/v0_no_token_balances"),
+        &explorer,
+    )
+    .unwrap();
+    transaction::attach_raw_instructions(
+        &mut result,
+        &fixture("transaction/v0_raw_view"),
+        &explorer,
+    )
+    .unwrap();
+    assert_snapshot("transaction/v0_raw_view", &result);
+}
+
+#[test]
+fn failed_transaction() {
+    transaction_snapshot("transaction/failed");
+}
+
+#[test]
+fn program_badges() {
+    transaction_snapshot("transaction/program_badges");
+}
+
+#[test]
+fn token_links_point_at_the_configured_explorer() {
+    let explorer = Url::parse("https://tools.internal/explorer/").unwrap();
+    let result = transaction::parse_transaction_html(
+        &fixture("transaction/legacy_token_balances"),
+        &explorer,
+    )
+    .unwrap();
+
+    let result = serde_json::to_value(&result).unwrap();
+    let token_balances = result["token_balances"].as_array().unwrap();
+    assert!(token_balances.iter().all(|x| x["token_url"]
+        .as_str()
+        .unwrap()
+        .starts_with("https://tools.internal/address/")));
+}
+
+#[test]
+fn executable_program_account() {
+    account_snapshot("account/executable_program");
+}
+
+#[test]
+fn account_without_history() {
+    account_snapshot("account/no_history");
+}
+
+#[test]
+fn token_account() {
+    account_snapshot("account/token_account");
+}
+
+#[test]
+fn token_mint() {
+    account_snapshot("account/mint");
+}
+
+#[test]
+fn fixed_supply_token_mint() {
+    account_snapshot("account/fixed_supply_mint");
+}
+
+#[test]
+fn upgradeable_program() {
+    account_snapshot("account/upgradeable_program");
+}
+
+#[test]
+fn program_security_txt() {
+    let result = account::parse_security_txt_html(&fixture("account/security_txt")).unwrap();
+    assert_snapshot("account/security_txt", &result);
+}
+
+#[test]
+fn immutable_program_data() {
+    account_snapshot("account/program_data");
+}
+
+#[test]
+fn delegated_stake_account() {
+    account_snapshot("account/stake_account");
+}
+
+#[test]
+fn stake_rewards() {
+    let result = account::parse_rewards_html(&fixture("account/rewards")).unwrap();
+    assert_snapshot("account/rewards", &result);
+}
+
+#[test]
+fn unrecognized_account_overview() {
+    account_snapshot("account/program_buffer");
+}
+
+#[test]
+fn vote_account() {
+    account_snapshot("account/vote_account");
+}
+
+#[test]
+fn nonce_account() {
+    account_snapshot("account/nonce_account");
+}
+
+#[test]
+fn address_lookup_table_account() {
+    account_snapshot("account/lookup_table");
+}
+
+#[test]
+fn token_holdings() {
+    let result =
+        account::parse_token_holdings_html(&fixture("account/token_holdings"), false).unwrap();
+    assert_snapshot("account/token_holdings", &result);
+}
+
+#[test]
+fn token_holdings_without_zero_balances() {
+    let result =
+        account::parse_token_holdings_html(&fixture("account/token_holdings"), true).unwrap();
+    assert_eq!(result.len(), 3);
+    assert!(result.iter().all(|x| x.balance.raw > 0));
+}
+
+#[test]
+fn account_history_stops_at_its_boundaries() {
+    let html = fixture("account/executable_program");
+    let signatures = |history: HistoryRange| {
+        account::parse_account_html(&html, &history)
+            .unwrap()
+            .recent_transactions
+            .into_iter()
+            .map(|x| x.signature[..8].to_string())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(signatures(HistoryRange::new(2)), ["5j7s6NiJ", "2HPp5Vjb"]);
+    assert_eq!(
+        signatures(HistoryRange {
+            until: Some("2HPp5Vjb3Jt3QhDb1PcHnZPJ3cNQ2fmv8YG7M7tJb5AYfVrwYhzUx2gYHAvuCphLf2NDLA7rcPLj8m1r1sdGbByP".to_string()),
+            ..HistoryRange::new(TX_LIMIT)
+        }),
+        ["5j7s6NiJ"]
+    );
+    assert_eq!(
+        signatures(HistoryRange {
+            since: Some(Utc.with_ymd_and_hms(2023, 3, 2, 10, 0, 0).unwrap()),
+            ..HistoryRange::new(TX_LIMIT)
+        }),
+        ["5j7s6NiJ", "2HPp5Vjb"]
+    );
+}